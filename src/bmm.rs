@@ -0,0 +1,225 @@
+//! Blind merged mining (BIP301) commitments and proofs.
+use crate::SidechainId;
+use bitcoin::hashes::Hash as _;
+
+/// Bytes following `OP_RETURN` in a coinbase output that commits to a
+/// sidechain block (h*).
+pub const BMM_COMMITMENT_HEADER: [u8; 4] = [0xD1, 0x61, 0x73, 0x68];
+
+/// Parse a coinbase output script as a BMM commitment, returning the
+/// sidechain number and the committed critical hash (h*).
+///
+/// The script is `OP_RETURN`, [`BMM_COMMITMENT_HEADER`], the 32 byte
+/// critical hash, then the sidechain number as a script number push.
+pub fn parse_bmm_commitment(script: &bitcoin::Script) -> Option<(SidechainId, bitcoin::BlockHash)> {
+    let bytes = script.as_bytes();
+    let rest = bytes.strip_prefix(&[bitcoin::opcodes::all::OP_RETURN.to_u8()])?;
+    let rest = rest.strip_prefix(&BMM_COMMITMENT_HEADER)?;
+    if rest.len() < 32 {
+        return None;
+    }
+    let (critical_hash, sidechain_number) = rest.split_at(32);
+    let critical_hash = bitcoin::BlockHash::from_slice(critical_hash).ok()?;
+    let sidechain_number = match sidechain_number {
+        // OP_0
+        [0x00] => 0,
+        [0x01, n] if *n < 0x80 => *n,
+        [0x02, n, 0x00] if *n >= 0x80 => *n,
+        _ => return None,
+    };
+    Some((SidechainId(sidechain_number), critical_hash))
+}
+
+/// Critical hash committed to by a coinbase for a sidechain, if any.
+pub fn coinbase_bmm_commitment(
+    coinbase: &bitcoin::Transaction,
+    sidechain_number: SidechainId,
+) -> Option<bitcoin::BlockHash> {
+    coinbase.output.iter().find_map(|output| {
+        parse_bmm_commitment(&output.script_pubkey)
+            .filter(|(n, _)| *n == sidechain_number)
+            .map(|(_, critical_hash)| critical_hash)
+    })
+}
+
+/// Evidence that a mainchain block commits to a sidechain block, which can
+/// be checked without trusting the daemon.
+#[derive(Debug, Clone)]
+pub struct BmmProof {
+    pub main_hash: bitcoin::BlockHash,
+    pub critical_hash: bitcoin::BlockHash,
+    pub sidechain_number: SidechainId,
+    pub coinbase: bitcoin::Transaction,
+    /// Proof that `coinbase` is in the block `main_hash`.
+    pub txout_proof: bitcoin::MerkleBlock,
+}
+
+impl BmmProof {
+    /// Check that the coinbase commits to `critical_hash` for
+    /// `sidechain_number`, and that the coinbase is the first transaction of
+    /// block `main_hash`.
+    pub fn verify(&self) -> bool {
+        if !self.coinbase.is_coin_base()
+            || self.txout_proof.header.block_hash() != self.main_hash
+            || coinbase_bmm_commitment(&self.coinbase, self.sidechain_number)
+                != Some(self.critical_hash)
+        {
+            return false;
+        }
+        let mut matches = Vec::new();
+        let mut indexes = Vec::new();
+        if self
+            .txout_proof
+            .extract_matches(&mut matches, &mut indexes)
+            .is_err()
+        {
+            return false;
+        }
+        matches == [self.coinbase.txid()] && indexes == [0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRITICAL_HASH: [u8; 32] = [0xab; 32];
+
+    /// A commitment script with `sidechain_number` encoded as given.
+    fn commitment_script(critical_hash: &[u8], sidechain_number: &[u8]) -> bitcoin::ScriptBuf {
+        let mut script = vec![bitcoin::opcodes::all::OP_RETURN.to_u8()];
+        script.extend_from_slice(&BMM_COMMITMENT_HEADER);
+        script.extend_from_slice(critical_hash);
+        script.extend_from_slice(sidechain_number);
+        bitcoin::ScriptBuf::from_bytes(script)
+    }
+
+    #[test]
+    fn parse_sidechain_numbers() {
+        let critical_hash = bitcoin::BlockHash::from_byte_array(CRITICAL_HASH);
+        for (encoded, sidechain_number) in [
+            (&[0x00][..], 0),
+            (&[0x01, 0x01], 1),
+            (&[0x01, 0x7f], 0x7f),
+            (&[0x02, 0x80, 0x00], 0x80),
+            (&[0x02, 0xff, 0x00], 0xff),
+        ] {
+            let script = commitment_script(&CRITICAL_HASH, encoded);
+            assert_eq!(
+                parse_bmm_commitment(&script),
+                Some((SidechainId(sidechain_number), critical_hash)),
+                "{encoded:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_invalid_commitments() {
+        for script in [
+            // Truncated critical hash, then no sidechain number.
+            commitment_script(&CRITICAL_HASH[..31], &[]),
+            commitment_script(&CRITICAL_HASH, &[]),
+            // Negative, or not minimally encoded.
+            commitment_script(&CRITICAL_HASH, &[0x01, 0x80]),
+            commitment_script(&CRITICAL_HASH, &[0x02, 0x01, 0x00]),
+            // Trailing bytes.
+            commitment_script(&CRITICAL_HASH, &[0x01, 0x01, 0x00]),
+        ] {
+            assert_eq!(parse_bmm_commitment(&script), None, "{script:?}");
+        }
+        let mut wrong_header = commitment_script(&CRITICAL_HASH, &[0x00]).into_bytes();
+        wrong_header[1] ^= 0xff;
+        let mut no_op_return = commitment_script(&CRITICAL_HASH, &[0x00]).into_bytes();
+        no_op_return[0] = bitcoin::opcodes::OP_TRUE.to_u8();
+        for script in [wrong_header, no_op_return] {
+            let script = bitcoin::ScriptBuf::from_bytes(script);
+            assert_eq!(parse_bmm_commitment(&script), None, "{script:?}");
+        }
+    }
+
+    fn transaction(
+        input: bitcoin::OutPoint,
+        script_pubkey: bitcoin::ScriptBuf,
+    ) -> bitcoin::Transaction {
+        bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: input,
+                ..Default::default()
+            }],
+            output: vec![bitcoin::TxOut {
+                value: 0,
+                script_pubkey,
+            }],
+        }
+    }
+
+    /// A proof for a block whose coinbase commits to [`CRITICAL_HASH`] for
+    /// sidechain 1, with the transactions matching `include` in its Merkle
+    /// proof.
+    fn proof(include: impl Fn(&bitcoin::Transaction) -> bool) -> BmmProof {
+        let coinbase = transaction(
+            bitcoin::OutPoint::null(),
+            commitment_script(&CRITICAL_HASH, &[0x01, 0x01]),
+        );
+        let spend = transaction(
+            bitcoin::OutPoint {
+                txid: bitcoin::Txid::from_byte_array([1; 32]),
+                vout: 0,
+            },
+            bitcoin::ScriptBuf::new(),
+        );
+        let mut block = bitcoin::Block {
+            header: bitcoin::block::Header {
+                version: bitcoin::block::Version::TWO,
+                prev_blockhash: bitcoin::BlockHash::all_zeros(),
+                merkle_root: bitcoin::hash_types::TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: bitcoin::CompactTarget::from_consensus(0x207fffff),
+                nonce: 0,
+            },
+            txdata: vec![coinbase.clone(), spend],
+        };
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        let included: Vec<_> = block
+            .txdata
+            .iter()
+            .filter(|transaction| include(transaction))
+            .map(bitcoin::Transaction::txid)
+            .collect();
+        let txout_proof =
+            bitcoin::MerkleBlock::from_block_with_predicate(&block, |txid| included.contains(txid));
+        BmmProof {
+            main_hash: block.block_hash(),
+            critical_hash: bitcoin::BlockHash::from_byte_array(CRITICAL_HASH),
+            sidechain_number: SidechainId(1),
+            coinbase,
+            txout_proof,
+        }
+    }
+
+    #[test]
+    fn verify_proof() {
+        let proof = proof(bitcoin::Transaction::is_coin_base);
+        assert!(proof.verify());
+
+        let mut tampered = proof.clone();
+        tampered.txout_proof.header.nonce += 1;
+        assert!(!tampered.verify());
+
+        let mut other_sidechain = proof.clone();
+        other_sidechain.sidechain_number = SidechainId(2);
+        assert!(!other_sidechain.verify());
+
+        let mut other_hash = proof;
+        other_hash.critical_hash = bitcoin::BlockHash::all_zeros();
+        assert!(!other_hash.verify());
+    }
+
+    #[test]
+    fn verify_proof_of_other_transaction() {
+        let proof = proof(|transaction| !transaction.is_coin_base());
+        assert!(!proof.verify());
+    }
+}
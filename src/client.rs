@@ -4,25 +4,25 @@ use std::ops::{Deref, DerefMut};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct WithdrawalStatus {
-    hash: bitcoin::Txid,
-    nblocksleft: usize,
-    nworkscore: usize,
+    pub hash: bitcoin::Txid,
+    pub nblocksleft: usize,
+    pub nworkscore: usize,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct SpentWithdrawal {
-    pub nsidechain: u8,
+    pub nsidechain: SidechainId,
     pub hash: bitcoin::Txid,
     pub hashblock: bitcoin::BlockHash,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct FailedWithdrawal {
-    pub nsidechain: u8,
+    pub nsidechain: SidechainId,
     pub hash: bitcoin::Txid,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Vote {
     Upvote,
@@ -30,29 +30,182 @@ pub enum Vote {
     Downvote,
 }
 
+/// Fields that not all daemon versions return are optional.
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Block {
     pub hash: bitcoin::BlockHash,
-    pub confirmations: usize,
+    /// -1 if the block is not on the active chain.
+    pub confirmations: i64,
     pub strippedsize: usize,
     pub size: usize,
     pub weight: usize,
     pub height: usize,
     pub version: i32,
-    pub version_hex: String,
+    #[serde(default)]
+    pub version_hex: Option<String>,
     pub merkleroot: bitcoin::hash_types::TxMerkleNode,
     pub tx: Vec<bitcoin::Txid>,
     pub time: u32,
-    pub mediantime: u32,
+    #[serde(default)]
+    pub mediantime: Option<u32>,
     pub nonce: u32,
     pub bits: String,
-    pub difficulty: f64,
-    pub chainwork: String,
+    #[serde(default)]
+    pub difficulty: Option<f64>,
+    #[serde(default)]
+    pub chainwork: Option<String>,
     pub previousblockhash: Option<bitcoin::BlockHash>,
     pub nextblockhash: Option<bitcoin::BlockHash>,
 }
 
+/// Proof of work fields of a block, with `bits` parsed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BlockWork {
+    pub bits: bitcoin::CompactTarget,
+    pub target: bitcoin::Target,
+    /// Difficulty reported by the daemon.
+    pub difficulty: Option<f64>,
+    pub nonce: u32,
+}
+
+impl BlockWork {
+    /// Whether the reported difficulty agrees with the difficulty computed
+    /// from `bits`. `None` if the daemon did not report a difficulty.
+    pub fn difficulty_matches_bits(&self) -> Option<bool> {
+        let difficulty = self.difficulty?;
+        let expected = self.target.difficulty_float();
+        Some((difficulty - expected).abs() <= expected * 1e-9)
+    }
+}
+
+impl Block {
+    /// Proof of work fields, or `None` if `bits` is not valid hex.
+    pub fn work(&self) -> Option<BlockWork> {
+        let bits = u32::from_str_radix(&self.bits, 16).ok()?;
+        let bits = bitcoin::CompactTarget::from_consensus(bits);
+        Some(BlockWork {
+            bits,
+            target: bitcoin::Target::from_compact(bits),
+            difficulty: self.difficulty,
+            nonce: self.nonce,
+        })
+    }
+}
+
+/// Fields of a verbose `getrawtransaction` response.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct TransactionInfo {
+    pub txid: bitcoin::Txid,
+    pub hex: String,
+    /// Absent if the transaction is unconfirmed.
+    #[serde(default)]
+    pub blockhash: Option<bitcoin::BlockHash>,
+    #[serde(default)]
+    pub confirmations: Option<u32>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(from = "RawBlockchainInfo", into = "RawBlockchainInfo")]
+pub struct BlockchainInfo {
+    /// `None` if the daemon reports a chain that [`bitcoin::Network`] has no
+    /// variant for, eg. a custom signet.
+    pub chain: Option<bitcoin::Network>,
+    /// The chain name as reported by the daemon, eg. "main".
+    pub raw_chain: String,
+    pub blocks: usize,
+    pub headers: usize,
+    pub bestblockhash: bitcoin::BlockHash,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RawBlockchainInfo {
+    chain: String,
+    blocks: usize,
+    headers: usize,
+    bestblockhash: bitcoin::BlockHash,
+}
+
+impl From<RawBlockchainInfo> for BlockchainInfo {
+    fn from(raw: RawBlockchainInfo) -> Self {
+        BlockchainInfo {
+            chain: bitcoin::Network::from_core_arg(&raw.chain).ok(),
+            raw_chain: raw.chain,
+            blocks: raw.blocks,
+            headers: raw.headers,
+            bestblockhash: raw.bestblockhash,
+        }
+    }
+}
+
+impl From<BlockchainInfo> for RawBlockchainInfo {
+    fn from(info: BlockchainInfo) -> Self {
+        RawBlockchainInfo {
+            chain: info.raw_chain,
+            blocks: info.blocks,
+            headers: info.headers,
+            bestblockhash: info.bestblockhash,
+        }
+    }
+}
+
+/// The output holding a sidechain's funds on the mainchain. Every deposit and
+/// withdrawal bundle spends the previous CTIP and creates a new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Ctip {
+    pub txid: bitcoin::Txid,
+    #[serde(alias = "n")]
+    pub vout: u32,
+    #[serde(
+        serialize_with = "bitcoin::amount::serde::as_btc::serialize",
+        deserialize_with = "deserialize_btc"
+    )]
+    pub amount: bitcoin::Amount,
+}
+
+impl Ctip {
+    pub fn outpoint(&self) -> bitcoin::OutPoint {
+        bitcoin::OutPoint {
+            txid: self.txid,
+            vout: self.vout,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct SmartFeeEstimate {
+    /// Fee rate per kvB.
+    #[serde(
+        default,
+        serialize_with = "bitcoin::amount::serde::as_btc::opt::serialize",
+        deserialize_with = "deserialize_btc_opt"
+    )]
+    pub feerate: Option<bitcoin::Amount>,
+    #[serde(default)]
+    pub errors: Vec<String>,
+    pub blocks: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressType {
+    Legacy,
+    P2shSegwit,
+    Bech32,
+    Bech32m,
+}
+
+impl AddressType {
+    /// Name of the address type, as accepted by `getnewaddress`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressType::Legacy => "legacy",
+            AddressType::P2shSegwit => "p2sh-segwit",
+            AddressType::Bech32 => "bech32",
+            AddressType::Bech32m => "bech32m",
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Deposit {
@@ -63,6 +216,121 @@ pub struct Deposit {
     pub txhex: String,
 }
 
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct SidechainId(pub u8);
+
+impl From<u8> for SidechainId {
+    fn from(other: u8) -> SidechainId {
+        SidechainId(other)
+    }
+}
+
+impl From<SidechainId> for u8 {
+    fn from(other: SidechainId) -> u8 {
+        other.0
+    }
+}
+
+impl std::fmt::Display for SidechainId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SidechainProposal {
+    pub nsidechain: SidechainId,
+    pub title: String,
+    pub description: String,
+    #[serde(alias = "nversion")]
+    pub version: i32,
+    #[serde(alias = "hashID1")]
+    pub hashid1: String,
+    #[serde(alias = "hashID2")]
+    pub hashid2: String,
+}
+
+/// Daemon versions differ in field names. `nversion`, `hashID1` and
+/// `hashID2` are accepted for `version`, `hashid1` and `hashid2`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SidechainInfo {
+    pub nsidechain: SidechainId,
+    pub title: String,
+    pub description: String,
+    #[serde(alias = "nversion")]
+    pub version: i32,
+    #[serde(alias = "hashID1")]
+    pub hashid1: String,
+    #[serde(alias = "hashID2")]
+    pub hashid2: String,
+}
+
+impl From<SidechainInfo> for SidechainProposal {
+    fn from(info: SidechainInfo) -> Self {
+        SidechainProposal {
+            nsidechain: info.nsidechain,
+            title: info.title,
+            description: info.description,
+            version: info.version,
+            hashid1: info.hashid1,
+            hashid2: info.hashid2,
+        }
+    }
+}
+
+/// As with [`SidechainInfo`], `nage` and `nfail` are accepted for `age` and
+/// `fail`.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SidechainActivationStatus {
+    #[serde(flatten)]
+    pub proposal: SidechainProposal,
+    #[serde(alias = "nage")]
+    pub age: u32,
+    #[serde(alias = "nfail")]
+    pub fail: u32,
+}
+
+/// Where a sidechain proposal is in the activation process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProposalState {
+    /// Still being voted on.
+    Pending,
+    /// Activated as a sidechain.
+    Activated,
+    /// Failed activation, or no longer known to the daemon.
+    Rejected,
+}
+
+/// Number of blocks a sidechain proposal must age to activate.
+pub const SIDECHAIN_ACTIVATION_MAX_AGE: u32 = 2016;
+/// Number of blocks failing to ack a sidechain proposal after which it is
+/// rejected.
+pub const SIDECHAIN_ACTIVATION_MAX_FAILURES: u32 = 200;
+
+impl SidechainActivationStatus {
+    /// Blocks remaining until the proposal activates, if it is not failing.
+    pub fn blocks_until_activation(&self) -> Option<u32> {
+        if self.is_failing() {
+            return None;
+        }
+        Some(SIDECHAIN_ACTIVATION_MAX_AGE.saturating_sub(self.age))
+    }
+
+    /// Failures remaining before the proposal is rejected.
+    pub fn failures_remaining(&self) -> u32 {
+        SIDECHAIN_ACTIVATION_MAX_FAILURES.saturating_sub(self.fail)
+    }
+
+    /// Whether the proposal has failed to be acked too often, and will be
+    /// rejected.
+    pub fn is_failing(&self) -> bool {
+        self.fail >= SIDECHAIN_ACTIVATION_MAX_FAILURES
+    }
+}
+
 #[rpc(client)]
 pub trait Main {
     #[method(name = "stop")]
@@ -71,7 +339,7 @@ pub trait Main {
     #[method(name = "listwithdrawalstatus")]
     async fn listwithdrawalstatus(
         &self,
-        nsidechain: u8,
+        nsidechain: SidechainId,
     ) -> Result<Vec<WithdrawalStatus>, jsonrpsee::core::Error>;
     #[method(name = "listspentwithdrawals")]
     async fn listspentwithdrawals(&self) -> Result<Vec<SpentWithdrawal>, jsonrpsee::core::Error>;
@@ -81,19 +349,77 @@ pub trait Main {
     async fn getblockcount(&self) -> Result<usize, jsonrpsee::core::Error>;
     #[method(name = "getbestblockhash")]
     async fn getbestblockhash(&self) -> Result<bitcoin::BlockHash, jsonrpsee::core::Error>;
+    #[method(name = "getblockhash")]
+    async fn getblockhash(
+        &self,
+        height: usize,
+    ) -> Result<bitcoin::BlockHash, jsonrpsee::core::Error>;
+    #[method(name = "getrawmempool")]
+    async fn getrawmempool(&self) -> Result<Vec<bitcoin::Txid>, jsonrpsee::core::Error>;
+    /// Fails with code -5 if the transaction is not in the wallet.
+    #[method(name = "gettransaction")]
+    async fn gettransaction(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
+    #[method(name = "abandontransaction")]
+    async fn abandontransaction(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
+    #[method(name = "getblockchaininfo")]
+    async fn getblockchaininfo(&self) -> Result<BlockchainInfo, jsonrpsee::core::Error>;
     #[method(name = "getblock")]
     async fn getblock(
         &self,
         blockhash: &bitcoin::BlockHash,
         verbosity: Option<usize>,
     ) -> Result<Block, jsonrpsee::core::Error>;
+    /// Returns the serialized block hex. `verbosity` must be 0.
+    #[method(name = "getblock")]
+    async fn getblock_raw(
+        &self,
+        blockhash: &bitcoin::BlockHash,
+        verbosity: usize,
+    ) -> Result<String, jsonrpsee::core::Error>;
+    /// Returns the serialized header hex. `verbose` must be `false`.
+    #[method(name = "getblockheader")]
+    async fn getblockheader(
+        &self,
+        blockhash: &bitcoin::BlockHash,
+        verbose: bool,
+    ) -> Result<String, jsonrpsee::core::Error>;
+    /// Returns the raw transaction hex. `blockhash` is required to look up
+    /// transactions outside the mempool if the daemon has no txindex.
+    #[method(name = "getrawtransaction")]
+    async fn getrawtransaction(
+        &self,
+        txid: &bitcoin::Txid,
+        verbose: bool,
+        blockhash: Option<&bitcoin::BlockHash>,
+    ) -> Result<String, jsonrpsee::core::Error>;
+    /// `verbose` must be `true`.
+    #[method(name = "getrawtransaction")]
+    async fn getrawtransaction_verbose(
+        &self,
+        txid: &bitcoin::Txid,
+        verbose: bool,
+        blockhash: Option<&bitcoin::BlockHash>,
+    ) -> Result<TransactionInfo, jsonrpsee::core::Error>;
+    /// Returns the serialized merkle block hex.
+    #[method(name = "gettxoutproof")]
+    async fn gettxoutproof(
+        &self,
+        txids: &[bitcoin::Txid],
+        blockhash: Option<&bitcoin::BlockHash>,
+    ) -> Result<String, jsonrpsee::core::Error>;
     #[method(name = "createbmmcriticaldatatx")]
     async fn createbmmcriticaldatatx(
         &self,
         amount: AmountBtc,
         height: u32,
         criticalhash: &bitcoin::BlockHash,
-        nsidechain: u8,
+        nsidechain: SidechainId,
         prevbytes: &str,
     ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
     #[method(name = "verifybmm")]
@@ -101,13 +427,13 @@ pub trait Main {
         &self,
         blockhash: &bitcoin::BlockHash,
         criticalhash: &bitcoin::BlockHash,
-        nsidechain: u8,
+        nsidechain: SidechainId,
     ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
 
     #[method(name = "listsidechaindepositsbyblock")]
     async fn listsidechaindepositsbyblock(
         &self,
-        nsidechain: u8,
+        nsidechain: SidechainId,
         end_blockhash: Option<bitcoin::BlockHash>,
         start_blockhash: Option<bitcoin::BlockHash>,
     ) -> Result<Vec<Deposit>, jsonrpsee::core::Error>;
@@ -115,14 +441,26 @@ pub trait Main {
     #[method(name = "receivewithdrawalbundle")]
     async fn receivewithdrawalbundle(
         &self,
-        nsidechain: u8,
+        nsidechain: SidechainId,
         // Raw transaction hex.
         rawtx: &str,
     ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
 
+    /// Returns `None` if the block was accepted, and otherwise the reason it
+    /// was rejected.
+    #[method(name = "submitblock")]
+    async fn submitblock(&self, hexdata: &str) -> Result<Option<String>, jsonrpsee::core::Error>;
+
     #[method(name = "generate")]
     async fn generate(&self, num: u32) -> Result<serde_json::Value, jsonrpsee::core::Error>;
 
+    #[method(name = "generatetoaddress")]
+    async fn generatetoaddress(
+        &self,
+        nblocks: u32,
+        address: &bitcoin::Address,
+    ) -> Result<Vec<bitcoin::BlockHash>, jsonrpsee::core::Error>;
+
     #[method(name = "getnewaddress")]
     async fn getnewaddress(
         &self,
@@ -130,14 +468,55 @@ pub trait Main {
         address_type: &str,
     ) -> Result<bitcoin::Address<bitcoin::address::NetworkUnchecked>, jsonrpsee::core::Error>;
 
+    #[method(name = "listsidechainctip")]
+    async fn listsidechainctip(
+        &self,
+        nsidechain: SidechainId,
+    ) -> Result<Ctip, jsonrpsee::core::Error>;
+
+    #[method(name = "estimatesmartfee")]
+    async fn estimatesmartfee(
+        &self,
+        conf_target: u16,
+    ) -> Result<SmartFeeEstimate, jsonrpsee::core::Error>;
+
     #[method(name = "createsidechaindeposit")]
     async fn createsidechaindeposit(
         &self,
-        nsidechain: u8,
+        nsidechain: SidechainId,
         depositaddress: &str,
         amount: AmountBtc,
         fee: AmountBtc,
     ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
+
+    #[method(name = "setwithdrawalvote")]
+    async fn setwithdrawalvote(
+        &self,
+        vote: Vote,
+        nsidechain: SidechainId,
+        hash: &bitcoin::Txid,
+    ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
+
+    #[method(name = "listactivesidechains")]
+    async fn listactivesidechains(&self) -> Result<Vec<SidechainInfo>, jsonrpsee::core::Error>;
+
+    #[method(name = "listsidechainactivationstatus")]
+    async fn listsidechainactivationstatus(
+        &self,
+    ) -> Result<Vec<SidechainActivationStatus>, jsonrpsee::core::Error>;
+
+    #[method(name = "createsidechainproposal")]
+    async fn createsidechainproposal(
+        &self,
+        nsidechain: SidechainId,
+        title: &str,
+        description: &str,
+    ) -> Result<serde_json::Value, jsonrpsee::core::Error>;
+
+    #[method(name = "listsidechainproposals")]
+    async fn listsidechainproposals(
+        &self,
+    ) -> Result<Vec<SidechainProposal>, jsonrpsee::core::Error>;
 }
 
 // Arguments:
@@ -148,9 +527,11 @@ pub trait Main {
 // 4. "nsidechain"     (numeric, required) Sidechain requesting BMM
 // 5. "prevbytes"      (string, required) a portion of the previous block hash
 
-// FIXME: Make mainchain API machine friendly. Parsing human readable amounts
-// here is stupid -- just take and return values in satoshi.
-#[derive(Clone, Copy)]
+/// An amount that is serialized as a float number of BTC, which is how the
+/// mainchain daemon takes and returns amounts. This is only used at the RPC
+/// boundary -- the [`crate::Drivechain`] API always uses [`bitcoin::Amount`],
+/// serialized as an integer number of satoshis where needed.
+#[derive(Clone, Copy, Debug)]
 pub struct AmountBtc(pub bitcoin::Amount);
 
 impl From<bitcoin::Amount> for AmountBtc {
@@ -179,12 +560,44 @@ impl DerefMut for AmountBtc {
     }
 }
 
+/// Deserialize an amount of BTC given either as a number or, as some daemon
+/// versions return it, a numeric string.
+fn deserialize_btc<'de, D>(deserializer: D) -> Result<bitcoin::Amount, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(f64),
+        String(String),
+    }
+    match serde::Deserialize::deserialize(deserializer)? {
+        NumberOrString::Number(btc) => {
+            bitcoin::Amount::from_btc(btc).map_err(serde::de::Error::custom)
+        }
+        NumberOrString::String(btc) => {
+            bitcoin::Amount::from_str_in(&btc, bitcoin::Denomination::Bitcoin)
+                .map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// [`deserialize_btc`] for an optional amount.
+fn deserialize_btc_opt<'de, D>(deserializer: D) -> Result<Option<bitcoin::Amount>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let amount: Option<AmountBtc> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(amount.map(|amount| amount.0))
+}
+
 impl<'de> serde::Deserialize<'de> for AmountBtc {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        Ok(AmountBtc(bitcoin::Amount::des_btc(deserializer)?))
+        Ok(AmountBtc(deserialize_btc(deserializer)?))
     }
 }
 
@@ -196,3 +609,103 @@ impl serde::Serialize for AmountBtc {
         self.0.ser_btc(serializer)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn block_without_optional_fields() {
+        let block = json!({
+            "hash": "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+            "confirmations": 1,
+            "strippedsize": 285,
+            "size": 285,
+            "weight": 1140,
+            "height": 0,
+            "version": 1,
+            "merkleroot": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+            "tx": ["4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"],
+            "time": 1296688602,
+            "nonce": 2,
+            "bits": "207fffff",
+        });
+        let block: Block = serde_json::from_value(block).unwrap();
+        assert_eq!(block.height, 0);
+        assert_eq!(block.version_hex, None);
+        assert_eq!(block.mediantime, None);
+        assert_eq!(block.difficulty, None);
+        assert_eq!(block.chainwork, None);
+        assert_eq!(block.previousblockhash, None);
+        assert_eq!(block.nextblockhash, None);
+    }
+
+    #[test]
+    fn amount_number_or_string() {
+        let expected = bitcoin::Amount::from_sat(100_000);
+        for amount in [json!(0.001), json!("0.001")] {
+            let amount_btc: AmountBtc = serde_json::from_value(amount.clone()).unwrap();
+            assert_eq!(amount_btc.0, expected);
+            let ctip = json!({
+                "txid": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+                "vout": 0,
+                "amount": amount,
+            });
+            let ctip: Ctip = serde_json::from_value(ctip).unwrap();
+            assert_eq!(ctip.amount, expected);
+            let estimate = json!({ "feerate": amount, "blocks": 2 });
+            let estimate: SmartFeeEstimate = serde_json::from_value(estimate).unwrap();
+            assert_eq!(estimate.feerate, Some(expected));
+        }
+        let estimate = json!({ "errors": ["Insufficient data or no feerate found"], "blocks": 0 });
+        let estimate: SmartFeeEstimate = serde_json::from_value(estimate).unwrap();
+        assert_eq!(estimate.feerate, None);
+    }
+
+    /// Sidechain fields, with the names used by each daemon version.
+    fn sidechain_fixtures() -> [serde_json::Value; 2] {
+        [
+            json!({
+                "nsidechain": 1,
+                "title": "testchain",
+                "description": "a test sidechain",
+                "version": 0,
+                "hashid1": "aa",
+                "hashid2": "bb",
+                "age": 10,
+                "fail": 2,
+            }),
+            json!({
+                "nsidechain": 1,
+                "title": "testchain",
+                "description": "a test sidechain",
+                "nversion": 0,
+                "hashID1": "aa",
+                "hashID2": "bb",
+                "nage": 10,
+                "nfail": 2,
+            }),
+        ]
+    }
+
+    #[test]
+    fn sidechain_field_names() {
+        let expected = SidechainProposal {
+            nsidechain: SidechainId(1),
+            title: "testchain".to_owned(),
+            description: "a test sidechain".to_owned(),
+            version: 0,
+            hashid1: "aa".to_owned(),
+            hashid2: "bb".to_owned(),
+        };
+        for fixture in sidechain_fixtures() {
+            let info: SidechainInfo = serde_json::from_value(fixture.clone()).unwrap();
+            assert_eq!(SidechainProposal::from(info), expected, "{fixture}");
+            let status: SidechainActivationStatus =
+                serde_json::from_value(fixture.clone()).unwrap();
+            assert_eq!(status.proposal, expected, "{fixture}");
+            assert_eq!((status.age, status.fail), (10, 2), "{fixture}");
+        }
+    }
+}
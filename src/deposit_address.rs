@@ -0,0 +1,101 @@
+//! Deposit addresses, which identify the sidechain being deposited to and
+//! the address on that sidechain.
+//!
+//! A deposit address is `s{sidechain_number}_{address}_{checksum}`, where the
+//! checksum is the first 6 hex digits of the SHA-256 of everything before it.
+use crate::{Error, SidechainId};
+use bitcoin::hashes::{sha256, Hash as _, HashEngine as _};
+
+/// The checksum of a deposit address, as computed by the daemon: the first
+/// 3 bytes of the SHA-256 of `s{sidechain_number}_{payload}_`. It appears in
+/// the address hex encoded.
+pub fn deposit_address_checksum(sidechain_number: SidechainId, payload: &[u8]) -> [u8; 3] {
+    let mut engine = sha256::Hash::engine();
+    engine.input(format!("s{sidechain_number}_").as_bytes());
+    engine.input(payload);
+    engine.input(b"_");
+    let digest = sha256::Hash::from_engine(engine);
+    let mut checksum = [0; 3];
+    checksum.copy_from_slice(&digest[..3]);
+    checksum
+}
+
+/// Parse a deposit address into the sidechain number and the sidechain
+/// address, verifying the checksum. The sidechain number must be written as
+/// the daemon writes it, in decimal without a sign or leading zeros.
+pub fn parse_deposit_address(s: &str) -> Result<(SidechainId, Vec<u8>), Error> {
+    let invalid = || Error::InvalidDepositAddress {
+        address: s.to_owned(),
+    };
+    let (prefix, checksum) = s.rsplit_once('_').ok_or_else(invalid)?;
+    let (digits, address) = prefix
+        .strip_prefix('s')
+        .and_then(|rest| rest.split_once('_'))
+        .ok_or_else(invalid)?;
+    let sidechain_number = SidechainId(digits.parse().map_err(|_| invalid())?);
+    if digits != sidechain_number.to_string() || address.is_empty() {
+        return Err(invalid());
+    }
+    // The checksum is of the address as written, up to and including the
+    // last separator.
+    let digest = sha256::Hash::hash(&s.as_bytes()[..=prefix.len()]);
+    if !checksum.eq_ignore_ascii_case(&hex::encode(&digest[..3])) {
+        return Err(invalid());
+    }
+    Ok((sidechain_number, address.as_bytes().to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_known_answer() {
+        // First 3 bytes of SHA-256("s2_tmBd8jBwKvcVX3R1L3f6ytniBnvkmeGbHK_").
+        let payload = b"tmBd8jBwKvcVX3R1L3f6ytniBnvkmeGbHK";
+        assert_eq!(
+            deposit_address_checksum(SidechainId(2), payload),
+            [0x4c, 0xb2, 0x80]
+        );
+        let (sidechain_number, address) =
+            parse_deposit_address("s2_tmBd8jBwKvcVX3R1L3f6ytniBnvkmeGbHK_4cb280").unwrap();
+        assert_eq!(sidechain_number, SidechainId(2));
+        assert_eq!(address, payload);
+    }
+
+    /// `address` followed by the checksum of everything in it.
+    fn with_checksum(address: &str) -> String {
+        let digest = sha256::Hash::hash(address.as_bytes());
+        format!("{address}{}", hex::encode(&digest[..3]))
+    }
+
+    #[test]
+    fn non_canonical_sidechain_number() {
+        let payload = "tmBd8jBwKvcVX3R1L3f6ytniBnvkmeGbHK";
+        for number in ["+2", "02"] {
+            // With the checksum of the canonical form.
+            let address = format!("s{number}_{payload}_4cb280");
+            assert!(parse_deposit_address(&address).is_err(), "{address}");
+            // With the checksum of the address as written.
+            let address = with_checksum(&format!("s{number}_{payload}_"));
+            assert!(parse_deposit_address(&address).is_err(), "{address}");
+        }
+        assert!(parse_deposit_address(&with_checksum(&format!("s2_{payload}_"))).is_ok());
+    }
+
+    #[test]
+    fn round_trip() {
+        for (sidechain_number, payload) in [(0, "abc"), (255, "sidechain_address")] {
+            let sidechain_number = SidechainId(sidechain_number);
+            let checksum = deposit_address_checksum(sidechain_number, payload.as_bytes());
+            let address = format!("s{sidechain_number}_{payload}_{}", hex::encode(checksum));
+            assert_eq!(
+                parse_deposit_address(&address).unwrap(),
+                (sidechain_number, payload.as_bytes().to_vec())
+            );
+            let mut corrupted = address.clone();
+            corrupted.replace_range(1..2, if sidechain_number.0 == 0 { "1" } else { "0" });
+            assert!(parse_deposit_address(&corrupted).is_err());
+        }
+    }
+}
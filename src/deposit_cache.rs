@@ -0,0 +1,156 @@
+//! Local view of confirmed deposits that is rolled back on reorgs.
+use crate::{Output, TipEvent, TwoWayPegData};
+use std::collections::HashMap;
+
+/// Deposits from [`TwoWayPegData`], keyed by block. Blocks applied with
+/// [`Self::apply_block`], as from
+/// [`crate::Drivechain::two_way_peg_data_per_block`], are kept whether or not
+/// they have deposits, so that [`Self::rollback_to`] can find any of them as
+/// the fork point. Data spanning several blocks, as from
+/// [`crate::Drivechain::get_two_way_peg_data`], is kept under its last
+/// deposit block and removed as a whole.
+#[derive(Clone, Debug, Default)]
+pub struct DepositCache {
+    /// Blocks and their deposits, in the order applied.
+    blocks: Vec<(bitcoin::BlockHash, HashMap<bitcoin::OutPoint, Output>)>,
+}
+
+impl DepositCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the deposits in `two_way_peg_data`. Does nothing if there are
+    /// none.
+    pub fn apply(&mut self, two_way_peg_data: TwoWayPegData) {
+        if let Some(block_hash) = two_way_peg_data.deposit_block_hash {
+            self.apply_block(block_hash, two_way_peg_data);
+        }
+    }
+
+    /// Add block `block_hash` and the deposits in `two_way_peg_data`, which
+    /// must be the peg data of that block alone.
+    pub fn apply_block(&mut self, block_hash: bitcoin::BlockHash, two_way_peg_data: TwoWayPegData) {
+        match self.blocks.iter_mut().find(|(hash, _)| *hash == block_hash) {
+            Some((_, deposits)) => deposits.extend(two_way_peg_data.deposits),
+            None => self.blocks.push((block_hash, two_way_peg_data.deposits)),
+        }
+    }
+
+    /// Remove the deposits of blocks applied after `common_ancestor`,
+    /// returning whether it was found. It is only found if it was applied,
+    /// or connected by [`Self::apply_tip_event`].
+    pub fn rollback_to(&mut self, common_ancestor: bitcoin::BlockHash) -> bool {
+        match self
+            .blocks
+            .iter()
+            .position(|(hash, _)| *hash == common_ancestor)
+        {
+            Some(index) => {
+                self.blocks.truncate(index + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Follow an event from [`crate::Drivechain::follow_tip`]. A connected
+    /// block is kept, without deposits until they are applied. A
+    /// disconnected block is removed with its deposits; `follow_tip`
+    /// disconnects every block above the fork point.
+    pub fn apply_tip_event(&mut self, event: &TipEvent) {
+        match event {
+            TipEvent::Connected(block_hash) => {
+                if !self.blocks.iter().any(|(hash, _)| hash == block_hash) {
+                    self.blocks.push((*block_hash, HashMap::new()));
+                }
+            }
+            TipEvent::Disconnected(block_hash) => {
+                self.blocks.retain(|(hash, _)| hash != block_hash);
+            }
+        }
+    }
+
+    /// The last block applied, from which to fetch the next peg data.
+    pub fn tip(&self) -> Option<bitcoin::BlockHash> {
+        self.blocks.last().map(|(hash, _)| *hash)
+    }
+
+    pub fn get(&self, outpoint: &bitcoin::OutPoint) -> Option<&Output> {
+        self.blocks
+            .iter()
+            .find_map(|(_, deposits)| deposits.get(outpoint))
+    }
+
+    pub fn deposits(&self) -> impl Iterator<Item = (&bitcoin::OutPoint, &Output)> {
+        self.blocks.iter().flat_map(|(_, deposits)| deposits.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash as _;
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn rollback_to_block_without_deposits() {
+        use crate::{Drivechain, SidechainId, SimChain};
+        use futures::TryStreamExt as _;
+
+        let sim = SimChain::new();
+        let drivechain = Drivechain::from_client(SidechainId(0), sim.clone());
+        sim.add_deposit(SidechainId(0), "a", bitcoin::Amount::from_sat(1000));
+        let blocks = sim.mine(2);
+        sim.add_deposit(SidechainId(0), "b", bitcoin::Amount::from_sat(500));
+        sim.mine(1);
+        let tip = sim.tip();
+        let mut cache = DepositCache::new();
+        let per_block: Vec<_> = drivechain
+            .two_way_peg_data_per_block(tip, None)
+            .try_collect()
+            .await
+            .unwrap();
+        for (block_hash, two_way_peg_data) in per_block {
+            cache.apply_block(block_hash, two_way_peg_data);
+        }
+        assert_eq!(cache.deposits().count(), 2);
+        assert!(cache.rollback_to(blocks[1]));
+        let addresses: Vec<_> = cache
+            .deposits()
+            .map(|(_, output)| output.address.as_str())
+            .collect();
+        assert_eq!(addresses, ["a"]);
+        assert_eq!(cache.tip(), Some(blocks[1]));
+    }
+
+    #[test]
+    fn tip_events_roll_back_deposits() {
+        let block = |n: u8| bitcoin::BlockHash::from_byte_array([n; 32]);
+        let two_way_peg_data = |vout| TwoWayPegData {
+            deposits: HashMap::from([(
+                bitcoin::OutPoint {
+                    txid: bitcoin::Txid::from_byte_array([0; 32]),
+                    vout,
+                },
+                Output {
+                    address: "a".to_owned(),
+                    value: bitcoin::Amount::from_sat(1000),
+                },
+            )]),
+            deposit_block_hash: None,
+            bundle_statuses: HashMap::new(),
+            bundle_statuses_warning: None,
+        };
+        let mut cache = DepositCache::new();
+        cache.apply_tip_event(&TipEvent::Connected(block(1)));
+        cache.apply_block(block(1), two_way_peg_data(0));
+        cache.apply_tip_event(&TipEvent::Connected(block(2)));
+        cache.apply_tip_event(&TipEvent::Connected(block(3)));
+        cache.apply_block(block(3), two_way_peg_data(1));
+        assert!(cache.rollback_to(block(2)));
+        assert_eq!(cache.deposits().count(), 1);
+        cache.apply_tip_event(&TipEvent::Disconnected(block(1)));
+        assert_eq!(cache.deposits().count(), 0);
+    }
+}
@@ -1,18 +1,59 @@
+mod bmm;
 mod client;
-use base64::Engine as _;
+mod deposit_address;
+#[cfg(feature = "deposit-cache")]
+mod deposit_cache;
+mod rpc_client;
+mod self_test;
+#[cfg(feature = "sim")]
+mod sim;
+mod sync_store;
+#[cfg(test)]
+mod test_server;
 use bitcoin::consensus::{Decodable, Encodable};
-use jsonrpsee::http_client::{HeaderMap, HttpClient, HttpClientBuilder};
-use std::collections::HashMap;
+use futures::stream::{Stream, StreamExt as _, TryStreamExt as _};
+use jsonrpsee::core::client::ClientT;
+use jsonrpsee::core::params::BatchRequestBuilder;
+use jsonrpsee::rpc_params;
+use jsonrpsee::types::error::ErrorCode;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::SocketAddr;
+use std::ops::Bound;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 pub use bitcoin;
-pub use client::MainClient;
+pub use bmm::{coinbase_bmm_commitment, parse_bmm_commitment, BmmProof, BMM_COMMITMENT_HEADER};
+pub use client::{
+    AddressType, AmountBtc, Block, BlockWork, BlockchainInfo, Ctip, Deposit, FailedWithdrawal,
+    MainClient, ProposalState, SidechainActivationStatus, SidechainId, SidechainInfo,
+    SidechainProposal, SmartFeeEstimate, SpentWithdrawal, TransactionInfo, Vote, WithdrawalStatus,
+    SIDECHAIN_ACTIVATION_MAX_AGE, SIDECHAIN_ACTIVATION_MAX_FAILURES,
+};
+pub use deposit_address::{deposit_address_checksum, parse_deposit_address};
+#[cfg(feature = "deposit-cache")]
+pub use deposit_cache::DepositCache;
 pub use jsonrpsee;
+use rpc_client::HttpSettings;
+pub use rpc_client::{MethodNames, RpcClient};
+pub use self_test::{SelfTestReport, SelfTestStep};
+#[cfg(feature = "sim")]
+pub use sim::SimChain;
+#[cfg(feature = "file-store")]
+pub use sync_store::FileSyncStore;
+pub use sync_store::{MemorySyncStore, SyncBatch, SyncStore};
 
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum WithdrawalBundleStatus {
+    /// Received by the mainchain and being voted on.
+    Pending {
+        blocks_left: usize,
+        work_score: usize,
+    },
     Failed,
     Confirmed,
+    /// Not known to the mainchain, or its status could not be fetched.
+    Unknown,
 }
 
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -20,63 +61,1578 @@ pub struct TwoWayPegData {
     pub deposits: HashMap<bitcoin::OutPoint, Output>,
     pub deposit_block_hash: Option<bitcoin::BlockHash>,
     pub bundle_statuses: HashMap<bitcoin::Txid, WithdrawalBundleStatus>,
+    /// Set if only some of the bundle statuses could be fetched, describing
+    /// the error that caused the rest to be missing.
+    #[serde(default)]
+    pub bundle_statuses_warning: Option<String>,
 }
 
+#[cfg(feature = "bincode")]
+impl TwoWayPegData {
+    /// Serialize to a compact binary format. Outpoints, txids and block hashes
+    /// are encoded as raw bytes rather than hex strings.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// A deposit, along with the transaction and block it was made in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepositInfo {
+    pub outpoint: bitcoin::OutPoint,
+    pub output: Output,
+    /// The sidechain's CTIP value after this deposit.
+    pub ctip_total_after: bitcoin::Amount,
+    pub block_hash: bitcoin::BlockHash,
+    pub transaction: bitcoin::Transaction,
+}
+
+/// A change to the mainchain tip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TipEvent {
+    /// A block was added to the active chain.
+    Connected(bitcoin::BlockHash),
+    /// A block was removed from the active chain by a reorg.
+    Disconnected(bitcoin::BlockHash),
+}
+
+/// A change to this sidechain's confirmed deposits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepositUpdate {
+    /// A deposit was confirmed in a newly connected block.
+    Deposit(DepositInfo),
+    /// A block was disconnected, and any deposits it contained must be
+    /// rolled back.
+    Rollback { block_hash: bitcoin::BlockHash },
+}
+
+/// A change to this sidechain's unconfirmed deposits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepositEvent {
+    /// A deposit was seen in the mempool. The block it will be included in is
+    /// not known yet.
+    Pending {
+        outpoint: bitcoin::OutPoint,
+        output: Output,
+        transaction: bitcoin::Transaction,
+    },
+    /// A pending deposit was included in a block.
+    Confirmed {
+        outpoint: bitcoin::OutPoint,
+        block_hash: bitcoin::BlockHash,
+    },
+    /// A pending deposit was not confirmed in time, and is no longer watched.
+    Dropped { outpoint: bitcoin::OutPoint },
+}
+
+/// A deposit to this sidechain that is in the mempool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingDeposit {
+    pub outpoint: bitcoin::OutPoint,
+    pub output: Output,
+    pub transaction: bitcoin::Transaction,
+    /// Whether the deposit was made by this node's wallet, and so can be
+    /// abandoned with [`Drivechain::abandon_deposit`].
+    pub from_wallet: bool,
+}
+
+/// State of [`Drivechain::watch_deposits`].
+#[derive(Default)]
+struct MempoolWatch {
+    polled: bool,
+    ctip_script: Option<bitcoin::ScriptBuf>,
+    /// Mempool transactions that have already been checked for deposits.
+    checked: HashSet<bitcoin::Txid>,
+    /// Pending deposits, and when they were first seen.
+    pending: HashMap<bitcoin::OutPoint, Instant>,
+    events: VecDeque<DepositEvent>,
+}
+
+/// Why the daemon did not accept a submitted block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockRejection {
+    /// The block was already accepted.
+    Duplicate,
+    /// The block was already known, and is invalid.
+    DuplicateInvalid,
+    /// The block was already known, but not on the active chain.
+    DuplicateInconclusive,
+    /// The block was accepted, but not onto the active chain.
+    Inconclusive,
+    /// The block is invalid, for the reason given by the daemon, eg.
+    /// "bad-txnmrklroot".
+    Invalid(String),
+}
+
+impl From<String> for BlockRejection {
+    fn from(reason: String) -> Self {
+        match reason.as_str() {
+            "duplicate" => BlockRejection::Duplicate,
+            "duplicate-invalid" => BlockRejection::DuplicateInvalid,
+            "duplicate-inconclusive" => BlockRejection::DuplicateInconclusive,
+            "inconclusive" => BlockRejection::Inconclusive,
+            _ => BlockRejection::Invalid(reason),
+        }
+    }
+}
+
+/// Number of bytes of the previous mainchain block hash that a BMM request
+/// commits to. See [`bmm_prev_bytes`].
+pub const BMM_PREV_BYTES: usize = 4;
+
+/// The `prevbytes` argument of `createbmmcriticaldatatx` for a BMM request
+/// following `prev_main_hash`: the last [`BMM_PREV_BYTES`] bytes of the hash
+/// as it is displayed, so 8 hex characters.
+pub fn bmm_prev_bytes(prev_main_hash: &bitcoin::BlockHash) -> String {
+    let prev_main_hash = prev_main_hash.to_string();
+    prev_main_hash[prev_main_hash.len() - 2 * BMM_PREV_BYTES..].to_owned()
+}
+
+/// Parameters for a BMM request targeting the next mainchain block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BmmParams {
+    pub height: u32,
+    pub prev_main_hash: bitcoin::BlockHash,
+    pub prev_bytes: String,
+}
+
+/// Withdrawal bundle statuses, from
+/// [`Drivechain::get_withdrawal_bundle_statuses`].
+struct BundleStatuses {
+    statuses: HashMap<bitcoin::Txid, WithdrawalBundleStatus>,
+    /// Blocks that spent the confirmed bundles.
+    spent_in: HashMap<bitcoin::Txid, bitcoin::BlockHash>,
+    /// Set if some of the status lists could not be fetched.
+    warning: Option<String>,
+}
+
+/// Estimated virtual size of a deposit transaction, used to turn a fee rate
+/// into a deposit fee.
+const DEPOSIT_TX_VSIZE: u64 = 250;
+
+/// Maximum number of concurrent requests made when fetching many items.
+const DEFAULT_CONCURRENCY: usize = 16;
+
+/// Target interval between mainchain blocks, on networks other than regtest.
+const TARGET_BLOCK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Number of recent blocks averaged over by
+/// [`Drivechain::expected_block_interval`] on regtest.
+const RECENT_BLOCKS: usize = 10;
+
+/// Maximum number of addresses that [`Drivechain::getnewaddresses`] will
+/// generate at once.
+pub const MAX_NEW_ADDRESSES: u32 = 1000;
+
+/// Whether the daemon reported that the requested item does not exist.
+fn is_not_found(err: &jsonrpsee::core::Error) -> bool {
+    // RPC_INVALID_ADDRESS_OR_KEY
+    matches!(err, jsonrpsee::core::Error::Call(err) if err.code() == -5)
+}
+
+fn decode_transaction(txhex: &str) -> Result<bitcoin::Transaction, Error> {
+    let transaction = hex::decode(txhex)?;
+    Ok(bitcoin::Transaction::consensus_decode(
+        &mut std::io::Cursor::new(transaction),
+    )?)
+}
+
+/// The output of a deposit transaction that pays to the sidechain.
+fn burn_output<'a>(
+    transaction: &'a bitcoin::Transaction,
+    deposit: &Deposit,
+) -> Result<&'a bitcoin::TxOut, Error> {
+    transaction
+        .output
+        .get(deposit.nburnindex)
+        .ok_or(Error::InvalidDeposit {
+            block_hash: deposit.hashblock,
+            ntx: deposit.ntx,
+        })
+}
+
+/// Default limit on the size of RPC responses. Verbose `getblock` responses
+/// for full mainnet blocks can be tens of megabytes.
+pub const DEFAULT_MAX_RESPONSE_SIZE: u32 = 64 * 1024 * 1024;
+
+/// Builder for a [`Drivechain`] connected over HTTP, for settings beyond
+/// those taken by [`Drivechain::new_with_host`].
 #[derive(Clone)]
-pub struct Drivechain {
-    pub sidechain_number: u8,
-    pub client: HttpClient,
+pub struct DrivechainBuilder {
+    sidechain_number: SidechainId,
+    host: String,
+    user: String,
+    password: String,
+    settings: HttpSettings,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
-pub struct Output {
-    pub address: String,
-    pub value: u64,
-}
+impl DrivechainBuilder {
+    /// `host` is given as `hostname:port`.
+    pub fn new(sidechain_number: SidechainId, host: &str, user: &str, password: &str) -> Self {
+        DrivechainBuilder {
+            sidechain_number,
+            host: host.to_owned(),
+            user: user.to_owned(),
+            password: password.to_owned(),
+            settings: HttpSettings {
+                url: format!("http://{host}"),
+                max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+                request_timeout: Duration::from_secs(60),
+                connect_timeout: Duration::from_secs(10),
+                rate_limit_retries: 0,
+                rate_limit_backoff: Duration::from_secs(1),
+                block_cache_size: 0,
+                singleflight: false,
+            },
+        }
+    }
+
+    /// Maximum size of an RPC response, in bytes. Larger responses fail.
+    /// Defaults to [`DEFAULT_MAX_RESPONSE_SIZE`]. Raising it allows fetching
+    /// larger blocks, at the cost of buffering the whole response in memory.
+    pub fn max_response_size(mut self, max_response_size: u32) -> Self {
+        self.settings.max_response_size = max_response_size;
+        self
+    }
+
+    /// Retry requests up to `retries` times while the daemon, or a proxy in
+    /// front of it, responds with HTTP 429 or 503. The wait before each retry
+    /// starts at `backoff` and doubles, unless the response's `Retry-After`
+    /// header requests a longer wait. Defaults to no retries, in which case
+    /// such responses fail with [`Error::RateLimited`].
+    pub fn rate_limit_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.settings.rate_limit_retries = retries;
+        self.settings.rate_limit_backoff = backoff;
+        self
+    }
+
+    /// Cache raw `getblock` and `getblockheader` responses, which never
+    /// change for a given block hash, up to `size` bytes in total. The least
+    /// recently used responses are evicted to stay within `size`. Blocks are
+    /// cached hex encoded, so a full mainnet block takes several megabytes.
+    ///
+    /// The cache serves [`Drivechain::get_raw_block`],
+    /// [`Drivechain::get_block_header`], and the methods built on them, such
+    /// as [`Drivechain::follow_tip`] and
+    /// [`Drivechain::fetch_deposit_transactions`]. Verbose `getblock`
+    /// responses, as from [`Drivechain::stream_blocks`], are not cached, as
+    /// they include the block's confirmations. Defaults to 0, which disables
+    /// the cache.
+    pub fn block_cache_size(mut self, size: usize) -> Self {
+        self.settings.block_cache_size = size;
+        self
+    }
+
+    /// Share the response to a request between concurrent callers making the
+    /// identical request, so that only one of them reaches the daemon. Errors
+    /// are shared too. Only calls that read, such as `getblock` and the
+    /// `list*` calls, are shared, while wallet and mining calls always reach
+    /// the daemon. Defaults to off.
+    pub fn singleflight(mut self, singleflight: bool) -> Self {
+        self.settings.singleflight = singleflight;
+        self
+    }
+
+    /// Time to wait for a response to each request. Defaults to 60 seconds.
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.settings.request_timeout = request_timeout;
+        self
+    }
+
+    /// Time to wait for a TCP connection in [`Self::connect`]. Defaults to 10
+    /// seconds.
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.settings.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Build without connecting. Connections are made as requests are sent,
+    /// so an unreachable host fails each request after the request timeout.
+    pub fn build(self) -> Result<Drivechain, Error> {
+        let client = RpcClient::new(self.settings, &self.user, &self.password)?;
+        Ok(Drivechain::from_client(self.sidechain_number, client))
+    }
+
+    /// Check that the host accepts TCP connections within the connect
+    /// timeout, failing with [`Error::Unreachable`] otherwise, then build.
+    ///
+    /// The HTTP client has no connect timeout of its own, so this only
+    /// applies to this check, and not to connections made by later requests.
+    pub async fn connect(self) -> Result<Drivechain, Error> {
+        let unreachable = || Error::Unreachable {
+            host: self.host.clone(),
+        };
+        match tokio::time::timeout(
+            self.settings.connect_timeout,
+            tokio::net::TcpStream::connect(&self.host),
+        )
+        .await
+        {
+            Ok(Ok(_)) => (),
+            Ok(Err(_)) | Err(_) => return Err(unreachable()),
+        }
+        self.build()
+    }
+}
+
+/// Effective configuration of a [`Drivechain`], for logging. Does not
+/// include the password.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrivechainConfig {
+    pub url: String,
+    pub user: String,
+    pub sidechain_number: SidechainId,
+    pub max_response_size: u32,
+    pub request_timeout: Duration,
+    /// Only applies to [`DrivechainBuilder::connect`].
+    pub connect_timeout: Duration,
+    pub rate_limit_retries: u32,
+    pub rate_limit_backoff: Duration,
+    pub block_cache_size: usize,
+    pub singleflight: bool,
+    pub max_deposit_fee: Option<bitcoin::Amount>,
+}
+
+/// The script of the CTIP output of `transaction`.
+fn ctip_script<'a>(
+    ctip: &Ctip,
+    transaction: &'a bitcoin::Transaction,
+) -> Result<&'a bitcoin::Script, Error> {
+    transaction
+        .output
+        .get(ctip.vout as usize)
+        .map(|output| output.script_pubkey.as_script())
+        .ok_or(Error::InvalidCtip {
+            outpoint: ctip.outpoint(),
+        })
+}
+
+#[derive(Clone)]
+pub struct Drivechain<C = RpcClient> {
+    pub sidechain_number: SidechainId,
+    pub client: C,
+    // Network reported by the daemon, fetched on first use.
+    network: Arc<OnceLock<bitcoin::Network>>,
+    max_deposit_fee: Option<bitcoin::Amount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Output {
+    pub address: String,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub value: bitcoin::Amount,
+}
+
+impl<C> Drivechain<C> {
+    pub fn from_client(sidechain_number: SidechainId, client: C) -> Self {
+        Drivechain {
+            sidechain_number,
+            client,
+            network: Arc::new(OnceLock::new()),
+            max_deposit_fee: None,
+        }
+    }
+
+    /// Set the expected network, instead of fetching it from the daemon.
+    pub fn with_network(self, network: bitcoin::Network) -> Self {
+        Drivechain {
+            network: Arc::new(OnceLock::from(network)),
+            ..self
+        }
+    }
+
+    /// Refuse to create deposits paying a fee above `max_deposit_fee`.
+    pub fn with_max_deposit_fee(self, max_deposit_fee: bitcoin::Amount) -> Self {
+        Drivechain {
+            max_deposit_fee: Some(max_deposit_fee),
+            ..self
+        }
+    }
+}
+
+impl<C> Drivechain<C>
+where
+    C: ClientT + Send + Sync,
+{
+    pub async fn verify_bmm(
+        &self,
+        prev_main_hash: &bitcoin::BlockHash,
+        bmm_bytes: &bitcoin::BlockHash,
+    ) -> Result<(), Error> {
+        let prev_main_block = self.client.getblock(prev_main_hash, None).await?;
+        if prev_main_block.confirmations < 0 {
+            return Err(Error::PrevBlockReorgedOut {
+                prev_main_hash: *prev_main_hash,
+            });
+        }
+        let main_hash = prev_main_block.nextblockhash.ok_or(Error::NoNextBlock {
+            prev_main_hash: *prev_main_hash,
+        })?;
+        self.client
+            .verifybmm(&main_hash, bmm_bytes, self.sidechain_number)
+            .await?;
+        Ok(())
+    }
+
+    /// Ask the daemon to verify that `main_hash` commits to `critical_hash`,
+    /// and fetch a [`BmmProof`] that can be checked independently with
+    /// [`BmmProof::verify`].
+    pub async fn get_bmm_proof(
+        &self,
+        main_hash: &bitcoin::BlockHash,
+        critical_hash: &bitcoin::BlockHash,
+    ) -> Result<BmmProof, Error> {
+        self.client
+            .verifybmm(main_hash, critical_hash, self.sidechain_number)
+            .await?;
+        let coinbase = self.get_coinbase(main_hash).await?;
+        let txout_proof = self
+            .client
+            .gettxoutproof(&[coinbase.txid()], Some(main_hash))
+            .await?;
+        let txout_proof = bitcoin::MerkleBlock::consensus_decode(&mut std::io::Cursor::new(
+            hex::decode(txout_proof)?,
+        ))?;
+        Ok(BmmProof {
+            main_hash: *main_hash,
+            critical_hash: *critical_hash,
+            sidechain_number: self.sidechain_number,
+            coinbase,
+            txout_proof,
+        })
+    }
+
+    /// The critical hash (h*) that the coinbase of `main_hash` commits to for
+    /// `sidechain_number`, if any. Unlike [`Self::verify_bmm`], this checks
+    /// the coinbase itself rather than trusting the daemon's `verifybmm`.
+    pub async fn extract_bmm_commitment(
+        &self,
+        main_hash: &bitcoin::BlockHash,
+        sidechain_number: SidechainId,
+    ) -> Result<Option<bitcoin::BlockHash>, Error> {
+        let coinbase = self.get_coinbase(main_hash).await?;
+        Ok(coinbase_bmm_commitment(&coinbase, sidechain_number))
+    }
+
+    async fn get_coinbase(
+        &self,
+        main_hash: &bitcoin::BlockHash,
+    ) -> Result<bitcoin::Transaction, Error> {
+        let block = self.client.getblock(main_hash, None).await?;
+        let coinbase_txid = block.tx.first().ok_or(Error::NoCoinbase {
+            main_hash: *main_hash,
+        })?;
+        let coinbase = self
+            .client
+            .getrawtransaction(coinbase_txid, false, Some(main_hash))
+            .await?;
+        decode_transaction(&coinbase)
+    }
+
+    pub async fn get_mainchain_tip(&self) -> Result<bitcoin::BlockHash, Error> {
+        Ok(self.client.getbestblockhash().await?)
+    }
+
+    /// The mainchain tip and its height. Prefer this over `getblockcount`
+    /// followed by `getblockhash`, which can return a hash from a different
+    /// height if a block arrives between the calls. The height is read from
+    /// the tip block itself, and the read is retried if the tip is reorged
+    /// out in the meantime.
+    pub async fn get_tip_checked(&self) -> Result<(bitcoin::BlockHash, u32), Error> {
+        const MAX_ATTEMPTS: usize = 3;
+        let mut attempts = 0;
+        loop {
+            let tip = self.get_mainchain_tip().await?;
+            let block = self.client.getblock(&tip, None).await?;
+            attempts += 1;
+            if block.confirmations >= 0 {
+                return Ok((tip, block.height as u32));
+            }
+            if attempts == MAX_ATTEMPTS {
+                return Err(Error::BlockNotActive { block_hash: tip });
+            }
+        }
+    }
+
+    /// Median time past of the mainchain tip.
+    pub async fn median_time_past(&self) -> Result<u32, Error> {
+        let tip = self.get_mainchain_tip().await?;
+        self.client
+            .getblock(&tip, None)
+            .await?
+            .mediantime
+            .ok_or(Error::MissingField {
+                field: "mediantime",
+            })
+    }
+
+    /// Compute BMM parameters for the block following the current mainchain
+    /// tip.
+    pub async fn next_bmm_params(&self) -> Result<BmmParams, Error> {
+        let prev_main_hash = self.get_mainchain_tip().await?;
+        let tip = self.client.getblock(&prev_main_hash, None).await?;
+        Ok(BmmParams {
+            height: tip.height as u32 + 1,
+            prev_main_hash,
+            prev_bytes: bmm_prev_bytes(&prev_main_hash),
+        })
+    }
+
+    pub async fn create_bmm_request(
+        &self,
+        amount: bitcoin::Amount,
+        params: &BmmParams,
+        critical_hash: &bitcoin::BlockHash,
+    ) -> Result<(), Error> {
+        self.client
+            .createbmmcriticaldatatx(
+                amount.into(),
+                params.height,
+                critical_hash,
+                self.sidechain_number,
+                &params.prev_bytes,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The full block `hash`, for validating it without trusting the daemon.
+    pub async fn get_raw_block(&self, hash: &bitcoin::BlockHash) -> Result<bitcoin::Block, Error> {
+        let block = self.client.getblock_raw(hash, 0).await?;
+        Ok(bitcoin::Block::consensus_decode(
+            &mut std::io::Cursor::new(hex::decode(block)?),
+        )?)
+    }
+
+    /// The header of block `hash`. Unlike the verbose block, this never
+    /// changes, so it can be served from the block cache.
+    pub async fn get_block_header(
+        &self,
+        hash: &bitcoin::BlockHash,
+    ) -> Result<bitcoin::block::Header, Error> {
+        let header = self.client.getblockheader(hash, false).await?;
+        Ok(bitcoin::block::Header::consensus_decode(
+            &mut std::io::Cursor::new(hex::decode(header)?),
+        )?)
+    }
+
+    pub async fn get_blockchain_info(&self) -> Result<BlockchainInfo, Error> {
+        Ok(self.client.getblockchaininfo().await?)
+    }
+
+    /// The daemon's network. Addresses returned by the daemon are validated
+    /// against it. Fails if the daemon's chain is not a known network, in
+    /// which case the network can be set with [`Self::with_network`].
+    pub async fn network(&self) -> Result<bitcoin::Network, Error> {
+        if let Some(network) = self.network.get() {
+            return Ok(*network);
+        }
+        let info = self.get_blockchain_info().await?;
+        let network = info.chain.ok_or(Error::UnknownChain {
+            chain: info.raw_chain,
+        })?;
+        Ok(*self.network.get_or_init(|| network))
+    }
+
+    /// How often mainchain blocks are expected, eg. for scheduling BMM
+    /// requests. This is the target interval of 10 minutes, except on
+    /// regtest, where blocks are mined on demand and the interval is instead
+    /// the average over recent blocks. That may be zero if blocks were mined
+    /// in bursts, and is the target interval if there are too few blocks.
+    pub async fn expected_block_interval(&self) -> Result<Duration, Error> {
+        if self.network().await? != bitcoin::Network::Regtest {
+            return Ok(TARGET_BLOCK_INTERVAL);
+        }
+        let tip_hash = self.get_mainchain_tip().await?;
+        let tip = self.client.getblock(&tip_hash, None).await?;
+        let blocks = tip.height.min(RECENT_BLOCKS);
+        if blocks == 0 {
+            return Ok(TARGET_BLOCK_INTERVAL);
+        }
+        let oldest_hash = self.client.getblockhash(tip.height - blocks).await?;
+        let oldest = self.client.getblock(&oldest_hash, None).await?;
+        let elapsed = tip.time.saturating_sub(oldest.time);
+        Ok(Duration::from_secs(elapsed.into()) / blocks as u32)
+    }
+
+    pub async fn get_new_address(
+        &self,
+        address_type: AddressType,
+    ) -> Result<bitcoin::Address, Error> {
+        let address = self.client.getnewaddress("", address_type.as_str()).await?;
+        Ok(address.require_network(self.network().await?)?)
+    }
+
+    /// `count` new wallet addresses, fetched in a single batch request.
+    /// The daemon still derives each address in turn, so this mostly saves
+    /// round trips. Fails if `count` exceeds [`MAX_NEW_ADDRESSES`].
+    pub async fn getnewaddresses(
+        &self,
+        count: u32,
+        address_type: AddressType,
+    ) -> Result<Vec<bitcoin::Address>, Error> {
+        if count > MAX_NEW_ADDRESSES {
+            return Err(Error::TooManyAddresses {
+                count,
+                max: MAX_NEW_ADDRESSES,
+            });
+        }
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let network = self.network().await?;
+        let mut batch = BatchRequestBuilder::new();
+        for _ in 0..count {
+            batch.insert("getnewaddress", rpc_params!["", address_type.as_str()])?;
+        }
+        let response = self
+            .client
+            .batch_request::<bitcoin::Address<bitcoin::address::NetworkUnchecked>>(batch)
+            .await?;
+        response
+            .into_iter()
+            .map(|address| {
+                let address =
+                    address.map_err(|err| jsonrpsee::core::Error::Call(err.into_owned()))?;
+                Ok(address.require_network(network)?)
+            })
+            .collect()
+    }
+
+    /// Submit a block, eg. one with a specific BMM commitment in its coinbase.
+    pub async fn submit_block(&self, block: &bitcoin::Block) -> Result<(), Error> {
+        let mut raw_block = vec![];
+        block.consensus_encode(&mut raw_block)?;
+        match self.client.submitblock(&hex::encode(raw_block)).await? {
+            None => Ok(()),
+            Some(reason) => Err(Error::BlockRejected {
+                reason: reason.into(),
+            }),
+        }
+    }
+
+    /// Mine `num` blocks with the wallet. Daemons that no longer support
+    /// `generate` are handled by mining to a new wallet address instead.
+    pub async fn generate(&self, num: u32) -> Result<Vec<bitcoin::BlockHash>, Error> {
+        match self.client.generate(num).await {
+            Ok(block_hashes) => Ok(serde_json::from_value(block_hashes)?),
+            Err(jsonrpsee::core::Error::Call(err))
+                if err.code() == ErrorCode::MethodNotFound.code()
+                    || err.message().contains("deprecated") =>
+            {
+                let address = self.get_new_address(AddressType::Bech32).await?;
+                self.generate_to_address(num, &address).await
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Mine `num` blocks to `address`, returning their hashes.
+    pub async fn generate_to_address(
+        &self,
+        num: u32,
+        address: &bitcoin::Address,
+    ) -> Result<Vec<bitcoin::BlockHash>, Error> {
+        let network = self.network().await?;
+        let unchecked = bitcoin::Address::<bitcoin::address::NetworkUnchecked>::new(
+            address.network,
+            address.payload.clone(),
+        );
+        if !unchecked.is_valid_for_network(network) {
+            return Err(Error::WrongNetwork {
+                address: address.clone(),
+                network,
+            });
+        }
+        Ok(self.client.generatetoaddress(num, address).await?)
+    }
+
+    /// Stream the blocks at `heights` in order, with at most `concurrency`
+    /// `getblock` calls in flight at once. Higher concurrency syncs faster,
+    /// at the cost of more open connections and buffered blocks.
+    pub fn stream_blocks(
+        &self,
+        heights: std::ops::Range<usize>,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<Block, Error>> + '_ {
+        futures::stream::iter(heights)
+            .map(move |height| async move {
+                let hash = self.client.getblockhash(height).await?;
+                Ok(self.client.getblock(&hash, None).await?)
+            })
+            .buffered(concurrency.max(1))
+    }
+
+    /// Stream this sidechain's deposits in each block at `heights`, in
+    /// order, with at most `concurrency` blocks being fetched at once.
+    pub fn stream_block_deposits(
+        &self,
+        heights: std::ops::Range<usize>,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<(bitcoin::BlockHash, Vec<Deposit>), Error>> + '_ {
+        futures::stream::iter(heights)
+            .map(move |height| async move {
+                let hash = self.client.getblockhash(height).await?;
+                let deposits = self
+                    .client
+                    .listsidechaindepositsbyblock(self.sidechain_number, Some(hash), Some(hash))
+                    .await?;
+                Ok((hash, deposits))
+            })
+            .buffered(concurrency.max(1))
+    }
+
+    /// Fetch the funding transaction of each deposit, in order. The inline
+    /// `txhex` is used where present; otherwise the transaction is taken from
+    /// its raw block.
+    pub async fn fetch_deposit_transactions(
+        &self,
+        deposits: &[Deposit],
+    ) -> Result<Vec<bitcoin::Transaction>, Error> {
+        futures::stream::iter(deposits)
+            .map(|deposit| async move {
+                if !deposit.txhex.is_empty() {
+                    return decode_transaction(&deposit.txhex);
+                }
+                let mut block = self.get_raw_block(&deposit.hashblock).await?;
+                if deposit.ntx >= block.txdata.len() {
+                    return Err(Error::InvalidDeposit {
+                        block_hash: deposit.hashblock,
+                        ntx: deposit.ntx,
+                    });
+                }
+                Ok(block.txdata.swap_remove(deposit.ntx))
+            })
+            .buffered(DEFAULT_CONCURRENCY)
+            .try_collect()
+            .await
+    }
+
+    /// Recommended fee for a deposit to confirm within `conf_target` blocks,
+    /// based on the daemon's fee estimate.
+    pub async fn recommended_deposit_fee(
+        &self,
+        conf_target: u16,
+    ) -> Result<bitcoin::Amount, Error> {
+        let estimate = self.client.estimatesmartfee(conf_target).await?;
+        let feerate = estimate.feerate.ok_or(Error::NoFeeEstimate {
+            errors: estimate.errors,
+        })?;
+        Ok(bitcoin::Amount::from_sat(
+            feerate.to_sat() * DEPOSIT_TX_VSIZE / 1000,
+        ))
+    }
+
+    /// Deposit `amount` to `address` on this sidechain. If `fee` is `None`,
+    /// the fee recommended for confirmation within 6 blocks is used.
+    pub async fn create_deposit(
+        &self,
+        address: &str,
+        amount: bitcoin::Amount,
+        fee: Option<bitcoin::Amount>,
+    ) -> Result<(), Error> {
+        let fee = match fee {
+            Some(fee) => fee,
+            None => self.recommended_deposit_fee(6).await?,
+        };
+        if let Some(max) = self.max_deposit_fee {
+            if fee > max {
+                return Err(Error::FeeTooHigh { fee, max });
+            }
+        }
+        self.client
+            .createsidechaindeposit(self.sidechain_number, address, amount.into(), fee.into())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_ctip(&self) -> Result<Ctip, Error> {
+        Ok(self.client.listsidechainctip(self.sidechain_number).await?)
+    }
+
+    /// Total value held by this sidechain on the mainchain, which is the
+    /// value of its CTIP. If the daemon cannot report the CTIP, this is
+    /// derived instead from all deposits, less the decrease of the CTIP in
+    /// each confirmed withdrawal bundle, which covers both the bundle's
+    /// payouts and its mainchain fee.
+    pub async fn net_locked_value(&self) -> Result<bitcoin::Amount, Error> {
+        match self.get_ctip().await {
+            Ok(ctip) => return Ok(ctip.amount),
+            Err(Error::Jsonrpsee(jsonrpsee::core::Error::Call(_))) => (),
+            Err(err) => return Err(err),
+        }
+        let tip = self.get_mainchain_tip().await?;
+        let deposits = self.get_deposits(tip, Bound::Unbounded).await?;
+        let Some(first) = deposits.first() else {
+            return Ok(bitcoin::Amount::ZERO);
+        };
+        let ctip_script = &first.transaction.output[first.outpoint.vout as usize].script_pubkey;
+        let deposited: bitcoin::Amount = deposits.iter().map(|deposit| deposit.output.value).sum();
+        let mut withdrawn = 0;
+        for spent in self.client.listspentwithdrawals().await? {
+            if spent.nsidechain != self.sidechain_number {
+                continue;
+            }
+            let bundle = self.get_transaction(&spent.hash).await?;
+            let spent_ctip = self
+                .spent_ctip_value(&bundle, ctip_script, &HashMap::new())
+                .await?;
+            let new_ctip = bundle
+                .output
+                .iter()
+                .find(|output| output.script_pubkey == *ctip_script)
+                .map_or(0, |output| output.value);
+            withdrawn += spent_ctip.saturating_sub(new_ctip);
+        }
+        Ok(deposited
+            .checked_sub(bitcoin::Amount::from_sat(withdrawn))
+            .unwrap_or(bitcoin::Amount::ZERO))
+    }
+
+    async fn get_transaction(&self, txid: &bitcoin::Txid) -> Result<bitcoin::Transaction, Error> {
+        let txhex = self.client.getrawtransaction(txid, false, None).await?;
+        decode_transaction(&txhex)
+    }
+
+    /// The current CTIP and the transaction that created it.
+    async fn get_ctip_transaction(&self) -> Result<(Ctip, bitcoin::Transaction), Error> {
+        let ctip = self.get_ctip().await?;
+        let transaction = self.get_transaction(&ctip.txid).await?;
+        Ok((ctip, transaction))
+    }
+
+    /// The script of the current CTIP output, which every deposit and
+    /// withdrawal bundle pays back to.
+    pub async fn ctip_script_pubkey(&self) -> Result<bitcoin::ScriptBuf, Error> {
+        let (ctip, transaction) = self.get_ctip_transaction().await?;
+        Ok(ctip_script(&ctip, &transaction)?.to_owned())
+    }
+
+    /// The lineage of CTIPs, oldest first, ending at the current CTIP. The
+    /// walk stops at `from` (included) if given, and otherwise at the CTIP
+    /// created by the sidechain's first deposit. Fails with
+    /// [`Error::CtipNotInChain`] if `from` is not in the lineage.
+    ///
+    /// Each CTIP is found from the next one by looking for the input that
+    /// spends an output with the same script, so this requires the daemon to
+    /// be running with `-txindex`.
+    pub async fn ctip_chain(&self, from: Option<bitcoin::OutPoint>) -> Result<Vec<Ctip>, Error> {
+        let (ctip, mut transaction) = self.get_ctip_transaction().await?;
+        let script_pubkey = ctip_script(&ctip, &transaction)?.to_owned();
+        let mut chain = vec![ctip];
+        while Some(chain[chain.len() - 1].outpoint()) != from {
+            let mut prev_ctip = None;
+            for input in &transaction.input {
+                if input.previous_output.is_null() {
+                    continue;
+                }
+                let prev_transaction = self.get_transaction(&input.previous_output.txid).await?;
+                let is_ctip = prev_transaction
+                    .output
+                    .get(input.previous_output.vout as usize)
+                    .filter(|output| output.script_pubkey == script_pubkey)
+                    .map(|output| output.value);
+                if let Some(value) = is_ctip {
+                    prev_ctip = Some((
+                        Ctip {
+                            txid: input.previous_output.txid,
+                            vout: input.previous_output.vout,
+                            amount: bitcoin::Amount::from_sat(value),
+                        },
+                        prev_transaction,
+                    ));
+                    break;
+                }
+            }
+            let Some((prev_ctip, prev_transaction)) = prev_ctip else {
+                if let Some(outpoint) = from {
+                    return Err(Error::CtipNotInChain { outpoint });
+                }
+                break;
+            };
+            chain.push(prev_ctip);
+            transaction = prev_transaction;
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// The CTIP spent by the withdrawal bundle `bundle_txid`. Requires the
+    /// daemon to be running with `-txindex`.
+    pub async fn bundle_spent_ctip(
+        &self,
+        bundle_txid: &bitcoin::Txid,
+    ) -> Result<bitcoin::OutPoint, Error> {
+        let (ctip, transaction) = self.get_ctip_transaction().await?;
+        let script_pubkey = ctip_script(&ctip, &transaction)?;
+        let bundle = self.get_transaction(bundle_txid).await?;
+        for input in &bundle.input {
+            let prev_transaction = self.get_transaction(&input.previous_output.txid).await?;
+            let is_ctip = prev_transaction
+                .output
+                .get(input.previous_output.vout as usize)
+                .is_some_and(|output| output.script_pubkey.as_script() == script_pubkey);
+            if is_ctip {
+                return Ok(input.previous_output);
+            }
+        }
+        Err(Error::NoCtipInput { txid: *bundle_txid })
+    }
+
+    pub async fn list_active_sidechains(&self) -> Result<Vec<SidechainInfo>, Error> {
+        Ok(self.client.listactivesidechains().await?)
+    }
+
+    pub async fn list_sidechain_activation_status(
+        &self,
+    ) -> Result<Vec<SidechainActivationStatus>, Error> {
+        Ok(self.client.listsidechainactivationstatus().await?)
+    }
+
+    pub async fn list_sidechain_proposals(&self) -> Result<Vec<SidechainProposal>, Error> {
+        Ok(self.client.listsidechainproposals().await?)
+    }
+
+    /// Returns the proposal occupying this sidechain's slot: the active
+    /// sidechain if there is one, otherwise the proposal currently leading
+    /// activation (fewest failed blocks, then greatest age).
+    ///
+    /// Callers that committed to a particular proposal should compare it
+    /// against this result and halt if they diverge.
+    pub async fn active_proposal_for_slot(&self) -> Result<Option<SidechainProposal>, Error> {
+        let slot = self.sidechain_number;
+        let active = self
+            .list_active_sidechains()
+            .await?
+            .into_iter()
+            .find(|sidechain| sidechain.nsidechain == slot);
+        if let Some(active) = active {
+            return Ok(Some(active.into()));
+        }
+        let leading = self
+            .list_sidechain_activation_status()
+            .await?
+            .into_iter()
+            .filter(|status| status.proposal.nsidechain == slot)
+            .min_by_key(|status| (status.fail, std::cmp::Reverse(status.age)))
+            .map(|status| status.proposal);
+        Ok(leading)
+    }
+
+    /// Slots with neither an active sidechain nor a proposal that is still
+    /// being voted on, in ascending order.
+    pub async fn free_sidechain_slots(&self) -> Result<Vec<SidechainId>, Error> {
+        let mut occupied = HashSet::new();
+        for sidechain in self.list_active_sidechains().await? {
+            occupied.insert(sidechain.nsidechain);
+        }
+        for status in self.list_sidechain_activation_status().await? {
+            if !status.is_failing() {
+                occupied.insert(status.proposal.nsidechain);
+            }
+        }
+        Ok((0..=u8::MAX)
+            .map(SidechainId)
+            .filter(|slot| !occupied.contains(slot))
+            .collect())
+    }
+
+    /// Whether `proposal` is still being voted on, has activated, or has
+    /// been rejected.
+    pub async fn proposal_state(
+        &self,
+        proposal: &SidechainProposal,
+    ) -> Result<ProposalState, Error> {
+        let activated = self
+            .list_active_sidechains()
+            .await?
+            .into_iter()
+            .any(|sidechain| SidechainProposal::from(sidechain) == *proposal);
+        if activated {
+            return Ok(ProposalState::Activated);
+        }
+        let pending = self
+            .list_sidechain_activation_status()
+            .await?
+            .into_iter()
+            .any(|status| status.proposal == *proposal && !status.is_failing());
+        if pending {
+            return Ok(ProposalState::Pending);
+        }
+        Ok(ProposalState::Rejected)
+    }
+
+    /// Number of confirmations of a block. Negative if the block is no
+    /// longer on the active chain.
+    pub async fn confirmations_of(&self, block_hash: &bitcoin::BlockHash) -> Result<i64, Error> {
+        Ok(self.client.getblock(block_hash, None).await?.confirmations)
+    }
+
+    /// Events for blocks connected and disconnected from the mainchain tip,
+    /// checking for changes every `poll_interval`. Disconnected blocks are
+    /// reported before the blocks that replaced them. Blocks that are on the
+    /// chain when the stream starts are not reported.
+    pub fn follow_tip(
+        &self,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = Result<TipEvent, Error>> + '_ {
+        let state = (None, VecDeque::new());
+        futures::stream::unfold(state, move |(mut tip, mut pending)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), (tip, pending)));
+                }
+                let result = match tip {
+                    None => self.get_mainchain_tip().await.map(|new_tip| {
+                        tip = Some(new_tip);
+                    }),
+                    Some(old_tip) => {
+                        tokio::time::sleep(poll_interval).await;
+                        self.tip_events(old_tip).await.map(|(new_tip, events)| {
+                            tip = Some(new_tip);
+                            pending.extend(events);
+                        })
+                    }
+                };
+                if let Err(err) = result {
+                    return Some((Err(err), (tip, pending)));
+                }
+            }
+        })
+    }
+
+    /// The current tip, and the events that lead to it from `old_tip`.
+    async fn tip_events(
+        &self,
+        old_tip: bitcoin::BlockHash,
+    ) -> Result<(bitcoin::BlockHash, Vec<TipEvent>), Error> {
+        let new_tip = self.get_mainchain_tip().await?;
+        let mut events = Vec::new();
+        if new_tip == old_tip {
+            return Ok((new_tip, events));
+        }
+        let prev_block_hash = |block: Block| {
+            block.previousblockhash.ok_or(Error::MissingField {
+                field: "previousblockhash",
+            })
+        };
+        // Walk back from the old tip to the last block still on the active
+        // chain.
+        let mut fork = old_tip;
+        loop {
+            let block = self.client.getblock(&fork, None).await?;
+            if block.confirmations >= 0 {
+                break;
+            }
+            events.push(TipEvent::Disconnected(fork));
+            fork = prev_block_hash(block)?;
+        }
+        let mut connected = Vec::new();
+        let mut hash = new_tip;
+        while hash != fork {
+            connected.push(TipEvent::Connected(hash));
+            hash = self.get_block_header(&hash).await?.prev_blockhash;
+        }
+        events.extend(connected.into_iter().rev());
+        Ok((new_tip, events))
+    }
+
+    /// Deposits to this sidechain as blocks are connected to the mainchain
+    /// tip, and rollbacks as blocks are disconnected.
+    ///
+    /// Only the connected block is scanned, and the CTIP spent by its first
+    /// deposit is looked up with `getrawtransaction`, so this requires the
+    /// daemon to be running with `-txindex`.
+    pub fn subscribe_deposits(
+        &self,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = Result<DepositUpdate, Error>> + '_ {
+        let tip_events = Box::pin(self.follow_tip(poll_interval));
+        futures::stream::unfold(
+            (tip_events, VecDeque::new()),
+            move |(mut tip_events, mut pending)| async move {
+                loop {
+                    if let Some(update) = pending.pop_front() {
+                        return Some((Ok(update), (tip_events, pending)));
+                    }
+                    let result = match tip_events.next().await? {
+                        Err(err) => Err(err),
+                        Ok(TipEvent::Connected(block_hash)) => self
+                            .get_deposits(block_hash, Bound::Included(block_hash))
+                            .await
+                            .map(|deposits| {
+                                pending.extend(deposits.into_iter().map(DepositUpdate::Deposit))
+                            }),
+                        Ok(TipEvent::Disconnected(block_hash)) => {
+                            pending.push_back(DepositUpdate::Rollback { block_hash });
+                            Ok(())
+                        }
+                    };
+                    if let Err(err) = result {
+                        return Some((Err(err), (tip_events, pending)));
+                    }
+                }
+            },
+        )
+    }
+
+    /// Deposits to this sidechain as they enter the mempool, checking every
+    /// `poll_interval`. Each deposit is reported as pending once, then as
+    /// confirmed when it is included in a block, or as dropped if it is not
+    /// confirmed within `drop_after` of being seen.
+    ///
+    /// Deposits that are mined before they are seen in the mempool are not
+    /// reported; use [`Self::subscribe_deposits`] for confirmed deposits.
+    /// Like [`Self::list_pending_deposits`], this requires the daemon to be
+    /// running with `-txindex`.
+    pub fn watch_deposits(
+        &self,
+        poll_interval: Duration,
+        drop_after: Duration,
+    ) -> impl Stream<Item = Result<DepositEvent, Error>> + '_ {
+        futures::stream::unfold(MempoolWatch::default(), move |mut watch| async move {
+            loop {
+                if let Some(event) = watch.events.pop_front() {
+                    return Some((Ok(event), watch));
+                }
+                if watch.polled {
+                    tokio::time::sleep(poll_interval).await;
+                }
+                watch.polled = true;
+                if let Err(err) = self.poll_mempool_deposits(&mut watch, drop_after).await {
+                    return Some((Err(err), watch));
+                }
+            }
+        })
+    }
+
+    async fn poll_mempool_deposits(
+        &self,
+        watch: &mut MempoolWatch,
+        drop_after: Duration,
+    ) -> Result<(), Error> {
+        let ctip_script = match &watch.ctip_script {
+            Some(ctip_script) => ctip_script.clone(),
+            None => {
+                let (ctip, transaction) = self.get_ctip_transaction().await?;
+                let ctip_script = ctip_script(&ctip, &transaction)?.to_owned();
+                watch.ctip_script.insert(ctip_script).clone()
+            }
+        };
+        let mempool: HashSet<_> = self.client.getrawmempool().await?.into_iter().collect();
+        let ctip_script = &ctip_script;
+        let mut deposits = futures::stream::iter(
+            mempool
+                .iter()
+                .filter(|txid| !watch.checked.contains(*txid))
+                .copied()
+                .collect::<Vec<_>>(),
+        )
+        .map(|txid| async move {
+            let deposit = self.fetch_mempool_deposit(txid, ctip_script).await?;
+            Ok::<_, Error>((txid, deposit))
+        })
+        .buffer_unordered(DEFAULT_CONCURRENCY);
+        while let Some((txid, deposit)) = deposits.try_next().await? {
+            watch.checked.insert(txid);
+            let Some((outpoint, output, transaction)) = deposit else {
+                continue;
+            };
+            watch.pending.insert(outpoint, Instant::now());
+            watch.events.push_back(DepositEvent::Pending {
+                outpoint,
+                output,
+                transaction,
+            });
+        }
+        let pending = &watch.pending;
+        watch
+            .checked
+            .retain(|txid| mempool.contains(txid) || pending.keys().any(|o| o.txid == *txid));
+        let left_mempool: Vec<_> = pending
+            .keys()
+            .filter(|outpoint| !mempool.contains(&outpoint.txid))
+            .copied()
+            .collect();
+        for outpoint in left_mempool {
+            let block_hash = match self
+                .client
+                .getrawtransaction_verbose(&outpoint.txid, true, None)
+                .await
+            {
+                Ok(info) => info.blockhash,
+                Err(err) if is_not_found(&err) => None,
+                Err(err) => return Err(err.into()),
+            };
+            if let Some(block_hash) = block_hash {
+                watch.pending.remove(&outpoint);
+                watch.events.push_back(DepositEvent::Confirmed {
+                    outpoint,
+                    block_hash,
+                });
+            }
+        }
+        let dropped: Vec<_> = watch
+            .pending
+            .iter()
+            .filter(|(_, seen)| seen.elapsed() >= drop_after)
+            .map(|(outpoint, _)| *outpoint)
+            .collect();
+        for outpoint in dropped {
+            watch.pending.remove(&outpoint);
+            watch.events.push_back(DepositEvent::Dropped { outpoint });
+        }
+        Ok(())
+    }
+
+    /// Deposits to this sidechain in the mempool, whether made by this
+    /// node's wallet or by others.
+    ///
+    /// Every mempool transaction is fetched, several at a time, and the
+    /// transactions spent by those paying to the CTIP script are looked up
+    /// with `getrawtransaction`, so this requires the daemon to be running
+    /// with `-txindex`. To follow the mempool, [`Self::watch_deposits`] only
+    /// fetches transactions it has not seen before.
+    pub async fn list_pending_deposits(&self) -> Result<Vec<PendingDeposit>, Error> {
+        let (ctip, transaction) = self.get_ctip_transaction().await?;
+        let ctip_script = ctip_script(&ctip, &transaction)?.to_owned();
+        let ctip_script = &ctip_script;
+        futures::stream::iter(self.client.getrawmempool().await?)
+            .map(|txid| async move { self.pending_deposit(txid, ctip_script).await })
+            .buffered(DEFAULT_CONCURRENCY)
+            .try_filter_map(|pending| async move { Ok(pending) })
+            .try_collect()
+            .await
+    }
+
+    /// The deposit made by the mempool transaction `txid`, if it is one.
+    /// `None` too if the transaction left the mempool before it could be
+    /// fetched.
+    async fn fetch_mempool_deposit(
+        &self,
+        txid: bitcoin::Txid,
+        ctip_script: &bitcoin::Script,
+    ) -> Result<Option<(bitcoin::OutPoint, Output, bitcoin::Transaction)>, Error> {
+        let transaction = match self.get_transaction(&txid).await {
+            Ok(transaction) => transaction,
+            Err(Error::Jsonrpsee(err)) if is_not_found(&err) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let deposit = self.transaction_deposit(&transaction, ctip_script).await?;
+        Ok(deposit.map(|(outpoint, output)| (outpoint, output, transaction)))
+    }
+
+    /// The deposit made by the mempool transaction `txid`, if it is one, and
+    /// whether it was made by this node's wallet. `None` too if the
+    /// transaction has left the mempool.
+    async fn pending_deposit(
+        &self,
+        txid: bitcoin::Txid,
+        ctip_script: &bitcoin::Script,
+    ) -> Result<Option<PendingDeposit>, Error> {
+        let Some((outpoint, output, transaction)) =
+            self.fetch_mempool_deposit(txid, ctip_script).await?
+        else {
+            return Ok(None);
+        };
+        let from_wallet = match self.client.gettransaction(&txid).await {
+            Ok(_) => true,
+            Err(err) if is_not_found(&err) => false,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(PendingDeposit {
+            outpoint,
+            output,
+            transaction,
+            from_wallet,
+        }))
+    }
+
+    /// Mark a deposit made by this node's wallet as abandoned, so that its
+    /// inputs can be spent again. The deposit must no longer be in the
+    /// mempool, eg. after being evicted.
+    pub async fn abandon_deposit(&self, txid: &bitcoin::Txid) -> Result<(), Error> {
+        self.client.abandontransaction(txid).await?;
+        Ok(())
+    }
 
-impl Drivechain {
-    pub async fn verify_bmm(
+    /// The deposit to this sidechain made by the confirmed output `outpoint`,
+    /// or `None` if it is not one. Requires the daemon to be running with
+    /// `-txindex`.
+    pub async fn lookup_deposit(
         &self,
-        prev_main_hash: &bitcoin::BlockHash,
-        bmm_bytes: &bitcoin::BlockHash,
-    ) -> Result<(), Error> {
-        let main_hash = self
+        outpoint: &bitcoin::OutPoint,
+    ) -> Result<Option<Output>, Error> {
+        let info = match self
             .client
-            .getblock(prev_main_hash, None)
-            .await?
-            .nextblockhash
-            .ok_or(Error::NoNextBlock {
-                prev_main_hash: *prev_main_hash,
-            })?;
-        self.client
-            .verifybmm(&main_hash, bmm_bytes, self.sidechain_number)
+            .getrawtransaction_verbose(&outpoint.txid, true, None)
+            .await
+        {
+            Ok(info) => info,
+            Err(err) if is_not_found(&err) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        if info.confirmations.unwrap_or(0) == 0 {
+            return Ok(None);
+        }
+        let transaction = decode_transaction(&info.hex)?;
+        // All of a sidechain's CTIPs pay to the same script.
+        let ctip_script = self.ctip_script_pubkey().await?;
+        let deposit = self.transaction_deposit(&transaction, &ctip_script).await?;
+        Ok(deposit
+            .filter(|(deposit_outpoint, _)| deposit_outpoint == outpoint)
+            .map(|(_, output)| output))
+    }
+
+    /// The deposit made by a transaction, if it is one. The deposit's value
+    /// is the increase of the CTIP it creates over the CTIP it spends, and
+    /// its address is the data of its `OP_RETURN` output.
+    async fn transaction_deposit(
+        &self,
+        transaction: &bitcoin::Transaction,
+        ctip_script: &bitcoin::Script,
+    ) -> Result<Option<(bitcoin::OutPoint, Output)>, Error> {
+        let Some((vout, ctip)) = transaction
+            .output
+            .iter()
+            .enumerate()
+            .find(|(_, output)| output.script_pubkey.as_script() == ctip_script)
+        else {
+            return Ok(None);
+        };
+        let prev_total = self
+            .spent_ctip_value(transaction, ctip_script, &HashMap::new())
             .await?;
-        Ok(())
+        // Withdrawal bundles also spend the CTIP, but decrease it.
+        if ctip.value <= prev_total {
+            return Ok(None);
+        }
+        let address = transaction.output.iter().find_map(|output| {
+            if !output.script_pubkey.is_op_return() {
+                return None;
+            }
+            match output.script_pubkey.instructions().nth(1) {
+                Some(Ok(bitcoin::script::Instruction::PushBytes(data))) => {
+                    String::from_utf8(data.as_bytes().to_vec()).ok()
+                }
+                _ => None,
+            }
+        });
+        let Some(address) = address else {
+            return Ok(None);
+        };
+        let outpoint = bitcoin::OutPoint {
+            txid: transaction.txid(),
+            vout: vout as u32,
+        };
+        let output = Output {
+            address,
+            value: bitcoin::Amount::from_sat(ctip.value - prev_total),
+        };
+        Ok(Some((outpoint, output)))
     }
 
-    pub async fn get_mainchain_tip(&self) -> Result<bitcoin::BlockHash, Error> {
-        Ok(self.client.getbestblockhash().await?)
+    /// Value of the CTIP spent by `transaction`, an output paying to
+    /// `ctip_script`, or 0 if it spends none. Outputs in `known` are checked
+    /// first, and the others are looked up with `getrawtransaction`.
+    async fn spent_ctip_value(
+        &self,
+        transaction: &bitcoin::Transaction,
+        ctip_script: &bitcoin::Script,
+        known: &HashMap<bitcoin::OutPoint, u64>,
+    ) -> Result<u64, Error> {
+        let inputs = || {
+            transaction
+                .input
+                .iter()
+                .map(|input| input.previous_output)
+                .filter(|outpoint| !outpoint.is_null())
+        };
+        if let Some(value) = inputs().find_map(|outpoint| known.get(&outpoint)) {
+            return Ok(*value);
+        }
+        for outpoint in inputs() {
+            let prev_transaction = self.get_transaction(&outpoint.txid).await?;
+            let prev_ctip = prev_transaction
+                .output
+                .get(outpoint.vout as usize)
+                .filter(|output| output.script_pubkey.as_script() == ctip_script);
+            if let Some(prev_ctip) = prev_ctip {
+                return Ok(prev_ctip.value);
+            }
+        }
+        Ok(0)
+    }
+
+    /// Check that the deposit's transaction is in its block, using a Merkle
+    /// proof from the daemon. The proof's header must hash to the deposit's
+    /// block hash, and its Merkle root must match the block's. Returns
+    /// `false` if the proof is invalid.
+    pub async fn verify_deposit_inclusion(&self, deposit: &DepositInfo) -> Result<bool, Error> {
+        let txid = deposit.transaction.txid();
+        if deposit.outpoint.txid != txid {
+            return Ok(false);
+        }
+        let header = self.get_block_header(&deposit.block_hash).await?;
+        let txout_proof = self
+            .client
+            .gettxoutproof(&[txid], Some(&deposit.block_hash))
+            .await?;
+        let txout_proof = bitcoin::MerkleBlock::consensus_decode(&mut std::io::Cursor::new(
+            hex::decode(txout_proof)?,
+        ))?;
+        if txout_proof.header.block_hash() != deposit.block_hash
+            || txout_proof.header.merkle_root != header.merkle_root
+        {
+            return Ok(false);
+        }
+        let mut matches = Vec::new();
+        let mut indexes = Vec::new();
+        if txout_proof
+            .extract_matches(&mut matches, &mut indexes)
+            .is_err()
+        {
+            return Ok(false);
+        }
+        Ok(matches == [txid])
     }
 
+    /// Deposits after `start` (exclusive) up to and including `end`, and
+    /// current withdrawal bundle statuses. `start` is normally the
+    /// `deposit_block_hash` from the previous call.
     pub async fn get_two_way_peg_data(
         &self,
         end: bitcoin::BlockHash,
         start: Option<bitcoin::BlockHash>,
+    ) -> Result<TwoWayPegData, Error> {
+        let start = match start {
+            Some(start) => Bound::Excluded(start),
+            None => Bound::Unbounded,
+        };
+        self.get_two_way_peg_data_bounded(end, start).await
+    }
+
+    /// Like [`Self::get_two_way_peg_data`], with explicit control over
+    /// whether deposits in the `start` block are included.
+    pub async fn get_two_way_peg_data_bounded(
+        &self,
+        end: bitcoin::BlockHash,
+        start: Bound<bitcoin::BlockHash>,
     ) -> Result<TwoWayPegData, Error> {
         let (deposits, deposit_block_hash) = self.get_deposit_outputs(end, start).await?;
         let bundle_statuses = self.get_withdrawal_bundle_statuses().await?;
         let two_way_peg_data = TwoWayPegData {
             deposits,
             deposit_block_hash,
-            bundle_statuses,
+            bundle_statuses: bundle_statuses.statuses,
+            bundle_statuses_warning: bundle_statuses.warning,
         };
         Ok(two_way_peg_data)
     }
 
+    /// The status of the withdrawal bundle `txid`. `Unknown` if the bundle
+    /// is not in any of the status lists, including if a list could not be
+    /// fetched.
+    pub async fn bundle_status(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Result<WithdrawalBundleStatus, Error> {
+        let bundle_statuses = self.get_withdrawal_bundle_statuses().await?;
+        Ok(bundle_statuses
+            .statuses
+            .get(txid)
+            .copied()
+            .unwrap_or(WithdrawalBundleStatus::Unknown))
+    }
+
+    /// Like [`Self::get_two_way_peg_data`], split into one item per block
+    /// after `start` up to and including `end`, in order. `end` must be on the
+    /// active chain.
+    ///
+    /// Confirmed withdrawal bundles are reported with the block that spent
+    /// them. Other bundle statuses are not tied to a block, so their current
+    /// values are reported with `end`.
+    pub fn two_way_peg_data_per_block(
+        &self,
+        end: bitcoin::BlockHash,
+        start: Option<bitcoin::BlockHash>,
+    ) -> impl Stream<Item = Result<(bitcoin::BlockHash, TwoWayPegData), Error>> + '_ {
+        let setup = async move {
+            let end_block = self.client.getblock(&end, None).await?;
+            if end_block.confirmations < 0 {
+                return Err(Error::BlockNotActive { block_hash: end });
+            }
+            let (heights, bound) = match start {
+                Some(start) => {
+                    let start_height = self.client.getblock(&start, None).await?.height;
+                    (
+                        start_height + 1..end_block.height + 1,
+                        Bound::Excluded(start),
+                    )
+                }
+                None => (0..end_block.height + 1, Bound::Unbounded),
+            };
+            let mut deposits = HashMap::<_, HashMap<_, _>>::new();
+            for deposit in self.get_deposits(end, bound).await? {
+                deposits
+                    .entry(deposit.block_hash)
+                    .or_default()
+                    .insert(deposit.outpoint, deposit.output);
+            }
+            let BundleStatuses {
+                statuses: all_statuses,
+                spent_in,
+                warning,
+            } = self.get_withdrawal_bundle_statuses().await?;
+            let mut statuses = HashMap::<_, HashMap<_, _>>::new();
+            for (txid, status) in all_statuses {
+                let block_hash = match status {
+                    WithdrawalBundleStatus::Confirmed => spent_in.get(&txid).copied(),
+                    _ => None,
+                };
+                statuses
+                    .entry(block_hash.unwrap_or(end))
+                    .or_default()
+                    .insert(txid, status);
+            }
+            Ok((heights, deposits, statuses, warning))
+        };
+        futures::stream::once(setup)
+            .map_ok(move |(heights, mut deposits, mut statuses, warning)| {
+                futures::stream::iter(heights)
+                    .map(move |height| async move { self.client.getblockhash(height).await })
+                    .buffered(DEFAULT_CONCURRENCY)
+                    .map(move |block_hash| {
+                        let block_hash = block_hash?;
+                        let deposits = deposits.remove(&block_hash).unwrap_or_default();
+                        let two_way_peg_data = TwoWayPegData {
+                            deposit_block_hash: (!deposits.is_empty()).then_some(block_hash),
+                            deposits,
+                            bundle_statuses: statuses.remove(&block_hash).unwrap_or_default(),
+                            bundle_statuses_warning: warning.clone(),
+                        };
+                        Ok((block_hash, two_way_peg_data))
+                    })
+            })
+            .try_flatten()
+    }
+
+    /// Send a withdrawal bundle to the mainchain. Does nothing if the daemon
+    /// already has the bundle, whether pending, confirmed or failed, so that
+    /// broadcasts can be safely retried.
     pub async fn broadcast_withdrawal_bundle(
         &self,
         transaction: bitcoin::Transaction,
     ) -> Result<(), Error> {
+        let txid = transaction.txid();
+        let bundle_statuses = self.get_withdrawal_bundle_statuses().await?;
+        if bundle_statuses.statuses.contains_key(&txid) {
+            return Ok(());
+        }
         let mut rawtx = vec![];
         transaction.consensus_encode(&mut rawtx)?;
         let rawtx = hex::encode(&rawtx);
@@ -86,10 +1642,62 @@ impl Drivechain {
         Ok(())
     }
 
+    /// Set this node's vote on a withdrawal bundle for this sidechain. The
+    /// vote is included in blocks mined by this node.
+    pub async fn set_withdrawal_vote(&self, vote: Vote, hash: bitcoin::Txid) -> Result<(), Error> {
+        self.client
+            .setwithdrawalvote(vote, self.sidechain_number, &hash)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn upvote_bundle(&self, hash: bitcoin::Txid) -> Result<(), Error> {
+        self.set_withdrawal_vote(Vote::Upvote, hash).await
+    }
+
     async fn get_deposit_outputs(
+        &self,
+        end: bitcoin::BlockHash,
+        start: Bound<bitcoin::BlockHash>,
+    ) -> Result<
+        (
+            HashMap<bitcoin::OutPoint, Output>,
+            Option<bitcoin::BlockHash>,
+        ),
+        Error,
+    > {
+        self.get_sidechain_deposit_outputs(self.sidechain_number, end, start)
+            .await
+    }
+
+    /// Deposits to every active sidechain after `start` (exclusive) up to and
+    /// including `end`.
+    pub async fn all_deposits_by_block(
         &self,
         end: bitcoin::BlockHash,
         start: Option<bitcoin::BlockHash>,
+    ) -> Result<HashMap<SidechainId, HashMap<bitcoin::OutPoint, Output>>, Error> {
+        let start = match start {
+            Some(start) => Bound::Excluded(start),
+            None => Bound::Unbounded,
+        };
+        let mut all_deposits = HashMap::new();
+        for sidechain in self.list_active_sidechains().await? {
+            // Running CTIP totals are per sidechain, so each sidechain is
+            // scanned separately.
+            let (deposits, _) = self
+                .get_sidechain_deposit_outputs(sidechain.nsidechain, end, start)
+                .await?;
+            all_deposits.insert(sidechain.nsidechain, deposits);
+        }
+        Ok(all_deposits)
+    }
+
+    async fn get_sidechain_deposit_outputs(
+        &self,
+        sidechain_number: SidechainId,
+        end: bitcoin::BlockHash,
+        start: Bound<bitcoin::BlockHash>,
     ) -> Result<
         (
             HashMap<bitcoin::OutPoint, Output>,
@@ -98,86 +1706,288 @@ impl Drivechain {
         Error,
     > {
         let deposits = self
+            .get_sidechain_deposits(sidechain_number, end, start)
+            .await?;
+        let last_block_hash = deposits.last().map(|deposit| deposit.block_hash);
+        let outputs = deposits
+            .into_iter()
+            .map(|deposit| (deposit.outpoint, deposit.output))
+            .collect();
+        Ok((outputs, last_block_hash))
+    }
+
+    /// This sidechain's deposits after `start` up to and including `end`, in
+    /// the order they were made.
+    ///
+    /// Each deposit's value is the increase of the CTIP it creates over the
+    /// CTIP it spends. Unless the first deposit spends a CTIP created in
+    /// `start`, that CTIP is found with `getrawtransaction`, so a bounded
+    /// `start` requires the daemon to be running with `-txindex`.
+    pub async fn get_deposits(
+        &self,
+        end: bitcoin::BlockHash,
+        start: Bound<bitcoin::BlockHash>,
+    ) -> Result<Vec<DepositInfo>, Error> {
+        self.get_sidechain_deposits(self.sidechain_number, end, start)
+            .await
+    }
+
+    /// This sidechain's deposits made after the deposit `last`, up to the
+    /// mainchain tip, in the order they were made. All deposits if `last` is
+    /// `None`.
+    ///
+    /// The block containing `last` is found with `getrawtransaction`, so this
+    /// requires the daemon to be running with `-txindex`.
+    pub async fn deposits_since(
+        &self,
+        last: Option<bitcoin::OutPoint>,
+    ) -> Result<Vec<DepositInfo>, Error> {
+        let tip = self.get_mainchain_tip().await?;
+        let Some(last) = last else {
+            return self.get_deposits(tip, Bound::Unbounded).await;
+        };
+        let block_hash = self
+            .client
+            .getrawtransaction_verbose(&last.txid, true, None)
+            .await?
+            .blockhash
+            .ok_or(Error::MissingField { field: "blockhash" })?;
+        let deposits = self.get_deposits(tip, Bound::Included(block_hash)).await?;
+        let position = deposits
+            .iter()
+            .position(|deposit| deposit.outpoint == last)
+            .ok_or(Error::NotADeposit { outpoint: last })?;
+        Ok(deposits[position + 1..].to_vec())
+    }
+
+    async fn get_sidechain_deposits(
+        &self,
+        sidechain_number: SidechainId,
+        end: bitcoin::BlockHash,
+        start: Bound<bitcoin::BlockHash>,
+    ) -> Result<Vec<DepositInfo>, Error> {
+        // The daemon lists deposits in the start block too.
+        let (list_start, skip_start) = match start {
+            Bound::Included(start) => (Some(start), false),
+            Bound::Excluded(start) => (Some(start), true),
+            Bound::Unbounded => (None, false),
+        };
+        let mut deposits = self
             .client
-            .listsidechaindepositsbyblock(self.sidechain_number, Some(end), start)
+            .listsidechaindepositsbyblock(sidechain_number, Some(end), list_start)
             .await?;
-        let mut last_block_hash = None;
-        let mut last_total = 0;
-        let mut outputs = HashMap::new();
+        // Blocks in the order listed, and deposits within a block in the
+        // order they were made, whatever order the daemon listed them in.
+        let mut block_order = HashMap::new();
         for deposit in &deposits {
-            let transaction = hex::decode(&deposit.txhex)?;
-            let transaction =
-                bitcoin::Transaction::consensus_decode(&mut std::io::Cursor::new(transaction))?;
-            if let Some(start) = start {
-                if deposit.hashblock == start {
-                    last_total = transaction.output[deposit.nburnindex].value;
-                    continue;
-                }
-            }
-            let total = transaction.output[deposit.nburnindex].value;
-            if total < last_total {
-                last_total = total;
+            let len = block_order.len();
+            block_order.entry(deposit.hashblock).or_insert(len);
+        }
+        deposits.sort_by_key(|deposit| (block_order[&deposit.hashblock], deposit.ntx));
+        let transactions = deposits
+            .iter()
+            .map(|deposit| decode_transaction(&deposit.txhex))
+            .collect::<Result<Vec<_>, _>>()?;
+        // CTIPs created by the listed deposits, including those in an
+        // excluded start block.
+        let mut ctips = HashMap::new();
+        for (deposit, transaction) in deposits.iter().zip(&transactions) {
+            let outpoint = bitcoin::OutPoint {
+                txid: transaction.txid(),
+                vout: deposit.nburnindex as u32,
+            };
+            ctips.insert(outpoint, burn_output(transaction, deposit)?.value);
+        }
+        let mut last_total = None;
+        let mut deposit_infos = Vec::new();
+        let mut outpoints = HashSet::new();
+        for (deposit, transaction) in deposits.iter().zip(transactions) {
+            if skip_start && Some(deposit.hashblock) == list_start {
                 continue;
             }
-            let value = total - last_total;
+            let burn_output = burn_output(&transaction, deposit)?;
+            let total = burn_output.value;
             let outpoint = bitcoin::OutPoint {
                 txid: transaction.txid(),
                 vout: deposit.nburnindex as u32,
             };
-            last_total = total;
-            last_block_hash = Some(deposit.hashblock);
+            // Each deposit has its own txid, so a collision means the daemon
+            // returned the same deposit twice.
+            if !outpoints.insert(outpoint) {
+                return Err(Error::DuplicateDeposit { outpoint });
+            }
+            // The running total is seeded with the CTIP spent by the first
+            // deposit. Without a start, that is the sidechain's first
+            // deposit, which spends no CTIP.
+            let prev_total = match last_total {
+                Some(last_total) => last_total,
+                None if list_start.is_none() => 0,
+                None => {
+                    self.spent_ctip_value(&transaction, &burn_output.script_pubkey, &ctips)
+                        .await?
+                }
+            };
+            last_total = Some(total);
+            // A CTIP that did not grow moved funds without depositing any,
+            // as when a bundle is paid out.
+            if total <= prev_total {
+                continue;
+            }
+            let value = total - prev_total;
             let output = Output {
                 address: deposit.strdest.clone(),
-                value,
+                value: bitcoin::Amount::from_sat(value),
             };
-            outputs.insert(outpoint, output);
+            deposit_infos.push(DepositInfo {
+                outpoint,
+                output,
+                ctip_total_after: bitcoin::Amount::from_sat(total),
+                block_hash: deposit.hashblock,
+                transaction,
+            });
         }
-        Ok((outputs, last_block_hash))
+        Ok(deposit_infos)
     }
 
-    async fn get_withdrawal_bundle_statuses(
-        &self,
-    ) -> Result<HashMap<bitcoin::Txid, WithdrawalBundleStatus>, Error> {
+    /// Returns the statuses that could be fetched, along with a warning if
+    /// any of the status lists could not be. Fails only if none could be
+    /// fetched.
+    async fn get_withdrawal_bundle_statuses(&self) -> Result<BundleStatuses, Error> {
+        let pending = self
+            .client
+            .listwithdrawalstatus(self.sidechain_number)
+            .await;
+        let spent = self.client.listspentwithdrawals().await;
+        let failed = self.client.listfailedwithdrawals().await;
+        let (pending, spent, failed) = match (pending, spent, failed) {
+            (Err(err), Err(_), Err(_)) => return Err(err.into()),
+            results => results,
+        };
+        fn ok_or_warn<T>(
+            result: Result<Vec<T>, jsonrpsee::core::Error>,
+            list: &str,
+            warnings: &mut Vec<String>,
+        ) -> Vec<T> {
+            result.unwrap_or_else(|err| {
+                warnings.push(format!("failed to list {list} withdrawals: {err}"));
+                Vec::new()
+            })
+        }
+        let mut warnings = Vec::new();
+        let pending = ok_or_warn(pending, "pending", &mut warnings);
+        let spent = ok_or_warn(spent, "spent", &mut warnings);
+        let failed = ok_or_warn(failed, "failed", &mut warnings);
         let mut statuses = HashMap::new();
-        for spent in &self.client.listspentwithdrawals().await? {
+        let mut spent_in = HashMap::new();
+        for pending in &pending {
+            let status = WithdrawalBundleStatus::Pending {
+                blocks_left: pending.nblocksleft,
+                work_score: pending.nworkscore,
+            };
+            statuses.insert(pending.hash, status);
+        }
+        for spent in &spent {
             if spent.nsidechain == self.sidechain_number {
                 statuses.insert(spent.hash, WithdrawalBundleStatus::Confirmed);
+                spent_in.insert(spent.hash, spent.hashblock);
             }
         }
-        for failed in &self.client.listfailedwithdrawals().await? {
-            statuses.insert(failed.hash, WithdrawalBundleStatus::Failed);
+        for failed in &failed {
+            if failed.nsidechain == self.sidechain_number {
+                statuses.insert(failed.hash, WithdrawalBundleStatus::Failed);
+            }
         }
-        Ok(statuses)
+        let warning = (!warnings.is_empty()).then(|| warnings.join("; "));
+        Ok(BundleStatuses {
+            statuses,
+            spent_in,
+            warning,
+        })
     }
+}
 
+impl Drivechain {
     pub fn new(
-        sidechain_number: u8,
+        sidechain_number: SidechainId,
         main_addr: SocketAddr,
         user: &str,
         password: &str,
     ) -> Result<Self, Error> {
-        let mut headers = HeaderMap::new();
-        let auth = format!("{user}:{password}");
-        let header_value = format!(
-            "Basic {}",
-            base64::engine::general_purpose::STANDARD_NO_PAD.encode(auth)
-        )
-        .parse()?;
-        headers.insert("authorization", header_value);
-        let client = HttpClientBuilder::default()
-            .set_headers(headers.clone())
-            .build(format!("http://{main_addr}"))?;
-        Ok(Drivechain {
-            sidechain_number,
-            client,
-        })
+        Self::new_with_host(sidechain_number, &main_addr.to_string(), user, password)
+    }
+
+    /// Connect to the daemon at `host`, given as `hostname:port`. Unlike
+    /// [`Self::new`], the host need not be an IP address, eg. it can be a
+    /// `.onion` address.
+    ///
+    /// The HTTP client does not support SOCKS proxies, so reaching a `.onion`
+    /// address requires routing connections through Tor outside of this
+    /// crate, eg. with `torsocks` or Tor's `TransPort` and `DNSPort`.
+    pub fn new_with_host(
+        sidechain_number: SidechainId,
+        host: &str,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, Error> {
+        DrivechainBuilder::new(sidechain_number, host, user, password).build()
+    }
+
+    /// Replace the RPC credentials, without otherwise changing the client.
+    /// Clones of this `Drivechain` made before the update keep the old
+    /// credentials.
+    pub fn update_credentials(&mut self, user: &str, password: &str) -> Result<(), Error> {
+        self.client.update_credentials(user, password)
+    }
+
+    pub fn config_summary(&self) -> DrivechainConfig {
+        let settings = self.client.settings();
+        DrivechainConfig {
+            url: settings.url.clone(),
+            user: self.client.user().to_owned(),
+            sidechain_number: self.sidechain_number,
+            max_response_size: settings.max_response_size,
+            request_timeout: settings.request_timeout,
+            connect_timeout: settings.connect_timeout,
+            rate_limit_retries: settings.rate_limit_retries,
+            rate_limit_backoff: settings.rate_limit_backoff,
+            block_cache_size: settings.block_cache_size,
+            singleflight: settings.singleflight,
+            max_deposit_fee: self.max_deposit_fee,
+        }
+    }
+
+    /// Use `method_names` to call daemons that rename RPC methods.
+    pub fn with_method_names(mut self, method_names: MethodNames) -> Self {
+        self.client.set_method_names(method_names);
+        self
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("jsonrpsee error")]
-    Jsonrpsee(#[from] jsonrpsee::core::Error),
+    Jsonrpsee(#[source] jsonrpsee::core::Error),
+    /// A response could not be parsed as expected, eg. because the daemon
+    /// version returns a different shape. `raw` is the response, truncated
+    /// if large.
+    #[error("could not parse response to {method}")]
+    ResponseParse {
+        method: String,
+        raw: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The daemon rejected the RPC credentials (HTTP 401), or does not allow
+    /// RPC connections from this host (HTTP 403).
+    #[error("authentication failed with HTTP status {status_code}")]
+    AuthFailed { status_code: u16 },
+    #[error("could not connect to {host}")]
+    Unreachable { host: String },
+    /// The daemon, or a proxy in front of it, responded with HTTP 429 or 503.
+    /// `retry_after` is the delay requested by the `Retry-After` header, if
+    /// any.
+    #[error("rate limited")]
+    RateLimited { retry_after: Option<Duration> },
     #[error("header error")]
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
     #[error("bitcoin consensus encode error")]
@@ -188,6 +1998,709 @@ pub enum Error {
     Hex(#[from] hex::FromHexError),
     #[error("no next block for prev_main_hash = {prev_main_hash}")]
     NoNextBlock { prev_main_hash: bitcoin::BlockHash },
+    #[error("prev_main_hash = {prev_main_hash} is not on the active chain")]
+    PrevBlockReorgedOut { prev_main_hash: bitcoin::BlockHash },
+    #[error("block_hash = {block_hash} is not on the active chain")]
+    BlockNotActive { block_hash: bitcoin::BlockHash },
+    #[error("invalid deposit ntx = {ntx} in block = {block_hash}")]
+    InvalidDeposit {
+        block_hash: bitcoin::BlockHash,
+        ntx: usize,
+    },
+    #[error("duplicate deposit outpoint = {outpoint}")]
+    DuplicateDeposit { outpoint: bitcoin::OutPoint },
+    #[error("bitcoin address error")]
+    BitcoinAddress(#[from] bitcoin::address::Error),
+    #[error("address {address} is not valid for network {network}")]
+    WrongNetwork {
+        address: bitcoin::Address,
+        network: bitcoin::Network,
+    },
+    #[error("block rejected: {reason:?}")]
+    BlockRejected { reason: BlockRejection },
+    #[error("no coinbase in block main_hash = {main_hash}")]
+    NoCoinbase { main_hash: bitcoin::BlockHash },
+    #[error("no fee estimate available: {errors:?}")]
+    NoFeeEstimate { errors: Vec<String> },
+    #[error("fee {fee} exceeds maximum {max}")]
+    FeeTooHigh {
+        fee: bitcoin::Amount,
+        max: bitcoin::Amount,
+    },
+    #[error("requested {count} addresses, more than the maximum of {max}")]
+    TooManyAddresses { count: u32, max: u32 },
+    #[error("invalid ctip outpoint = {outpoint}")]
+    InvalidCtip { outpoint: bitcoin::OutPoint },
+    #[error("ctip outpoint = {outpoint} is not in the ctip chain")]
+    CtipNotInChain { outpoint: bitcoin::OutPoint },
+    #[error("transaction txid = {txid} does not spend a ctip")]
+    NoCtipInput { txid: bitcoin::Txid },
+    #[error("missing field {field} in daemon response")]
+    MissingField { field: &'static str },
+    #[error("invalid deposit address {address}")]
+    InvalidDepositAddress { address: String },
+    #[error("outpoint = {outpoint} is not a deposit")]
+    NotADeposit { outpoint: bitcoin::OutPoint },
+    #[error("unknown chain {chain}")]
+    UnknownChain { chain: String },
+    #[error("expected regtest, but the daemon is on {network}")]
+    NotRegtest { network: bitcoin::Network },
+    #[error("self test check failed: {check}")]
+    SelfTestCheck { check: &'static str },
+    #[error("sync store error")]
+    SyncStore(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("json error")]
+    SerdeJson(#[from] serde_json::Error),
     #[error("io error")]
     Io(#[from] std::io::Error),
+    #[cfg(feature = "bincode")]
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+}
+
+impl From<jsonrpsee::core::Error> for Error {
+    fn from(err: jsonrpsee::core::Error) -> Self {
+        let err = match err {
+            jsonrpsee::core::Error::Transport(err) => {
+                match err.downcast::<rpc_client::ResponseParseError>() {
+                    Ok(err) => {
+                        return Error::ResponseParse {
+                            method: err.method,
+                            raw: err.raw,
+                            source: err.source,
+                        }
+                    }
+                    Err(err) => jsonrpsee::core::Error::Transport(err),
+                }
+            }
+            err => err,
+        };
+        if let Some(err) = rpc_client::rate_limited(&err) {
+            return Error::RateLimited {
+                retry_after: err.retry_after,
+            };
+        }
+        match rpc_client::http_status(&err) {
+            Some(status_code @ (401 | 403)) => Error::AuthFailed { status_code },
+            _ => Error::Jsonrpsee(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash as _;
+    use jsonrpsee::core::client::BatchResponse;
+    use jsonrpsee::core::traits::ToRpcParams;
+    use jsonrpsee::types::error::ErrorObject;
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+    use test_server::Reply;
+
+    const SIDECHAIN: SidechainId = SidechainId(0);
+
+    /// A client answering each method with a fixed result.
+    #[derive(Default)]
+    struct Canned(HashMap<&'static str, serde_json::Value>);
+
+    impl Canned {
+        fn with(mut self, method: &'static str, result: serde_json::Value) -> Self {
+            self.0.insert(method, result);
+            self
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ClientT for Canned {
+        async fn notification<Params>(
+            &self,
+            _method: &str,
+            _params: Params,
+        ) -> Result<(), jsonrpsee::core::Error>
+        where
+            Params: ToRpcParams + Send,
+        {
+            Ok(())
+        }
+
+        async fn request<R, Params>(
+            &self,
+            method: &str,
+            _params: Params,
+        ) -> Result<R, jsonrpsee::core::Error>
+        where
+            R: DeserializeOwned,
+            Params: ToRpcParams + Send,
+        {
+            let result = self
+                .0
+                .get(method)
+                .ok_or_else(|| ErrorObject::from(ErrorCode::MethodNotFound))?;
+            Ok(serde_json::from_value(result.clone())?)
+        }
+
+        async fn batch_request<'a, R>(
+            &self,
+            _batch: BatchRequestBuilder<'a>,
+        ) -> Result<BatchResponse<'a, R>, jsonrpsee::core::Error>
+        where
+            R: DeserializeOwned + std::fmt::Debug + 'a,
+        {
+            Err(ErrorObject::from(ErrorCode::MethodNotFound).into())
+        }
+    }
+
+    #[tokio::test]
+    async fn bundle_statuses_of_other_sidechains_are_ignored() {
+        let txid = |byte| bitcoin::Txid::from_byte_array([byte; 32]);
+        let client = Canned::default()
+            .with("listwithdrawalstatus", json!([]))
+            .with(
+                "listspentwithdrawals",
+                json!([{
+                    "nsidechain": 1,
+                    "hash": txid(1),
+                    "hashblock": bitcoin::BlockHash::all_zeros(),
+                }]),
+            )
+            .with(
+                "listfailedwithdrawals",
+                json!([
+                    { "nsidechain": 1, "hash": txid(2) },
+                    { "nsidechain": 0, "hash": txid(3) },
+                ]),
+            );
+        let drivechain = Drivechain::from_client(SIDECHAIN, client);
+        let bundle_statuses = drivechain.get_withdrawal_bundle_statuses().await.unwrap();
+        assert_eq!(
+            bundle_statuses.statuses,
+            HashMap::from([(txid(3), WithdrawalBundleStatus::Failed)])
+        );
+    }
+
+    #[tokio::test]
+    async fn duplicate_deposit_is_an_error() {
+        let transaction = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![bitcoin::TxOut {
+                value: 1000,
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+        let deposit = Deposit {
+            hashblock: bitcoin::BlockHash::all_zeros(),
+            nburnindex: 0,
+            ntx: 1,
+            strdest: "a".to_owned(),
+            txhex: bitcoin::consensus::encode::serialize_hex(&transaction),
+        };
+        let deposits = serde_json::to_value([&deposit, &deposit]).unwrap();
+        let client = Canned::default().with("listsidechaindepositsbyblock", deposits);
+        let drivechain = Drivechain::from_client(SIDECHAIN, client);
+        let result = drivechain
+            .get_deposits(deposit.hashblock, Bound::Unbounded)
+            .await;
+        let outpoint = bitcoin::OutPoint {
+            txid: transaction.txid(),
+            vout: 0,
+        };
+        assert!(
+            matches!(result, Err(Error::DuplicateDeposit { outpoint: o }) if o == outpoint),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn bmm_prev_bytes_are_the_end_of_the_hash() {
+        // Regtest genesis block.
+        let hash: bitcoin::BlockHash =
+            "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206"
+                .parse()
+                .unwrap();
+        assert_eq!(bmm_prev_bytes(&hash), "466e2206");
+    }
+
+    #[tokio::test]
+    async fn connect_unroutable_host() {
+        let start = Instant::now();
+        // Reserved for documentation, so never routed.
+        let result = DrivechainBuilder::new(SIDECHAIN, "192.0.2.1:18443", "user", "password")
+            .connect_timeout(Duration::from_millis(100))
+            .connect()
+            .await;
+        assert!(
+            matches!(&result, Err(Error::Unreachable { host }) if host == "192.0.2.1:18443"),
+            "{:?}",
+            result.err()
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn config_summary_reports_timeouts() {
+        let drivechain = DrivechainBuilder::new(SIDECHAIN, "localhost:18443", "user", "password")
+            .request_timeout(Duration::from_secs(30))
+            .connect_timeout(Duration::from_secs(3))
+            .build()
+            .unwrap();
+        let config = drivechain.config_summary();
+        assert_eq!(config.request_timeout, Duration::from_secs(30));
+        assert_eq!(config.connect_timeout, Duration::from_secs(3));
+    }
+
+    const REGTEST_ADDRESS: &str = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+
+    /// Answer `getblockchaininfo` for regtest, and other calls with `handler`.
+    fn regtest_daemon(
+        handler: impl Fn(&serde_json::Value) -> Reply + Send + Sync + 'static,
+    ) -> impl Fn(&serde_json::Value) -> Reply + Send + Sync + 'static {
+        move |request| match request["method"].as_str() {
+            Some("getblockchaininfo") => Reply::result(
+                request,
+                json!({
+                    "chain": "regtest",
+                    "blocks": 0,
+                    "headers": 0,
+                    "bestblockhash": bitcoin::BlockHash::all_zeros(),
+                }),
+            ),
+            _ => handler(request),
+        }
+    }
+
+    fn http_drivechain(server: &test_server::TestServer) -> Drivechain {
+        DrivechainBuilder::new(SIDECHAIN, &server.host, "user", "password")
+            .build()
+            .unwrap()
+    }
+
+    /// A transaction creating a CTIP of `value` sats as its first output.
+    fn ctip_transaction(value: u64) -> bitcoin::Transaction {
+        bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: vec![bitcoin::TxOut {
+                value,
+                script_pubkey: bitcoin::ScriptBuf::from_bytes(vec![0x51]),
+            }],
+        }
+    }
+
+    /// Answer `getrawtransaction` with `transactions`, and as the daemon
+    /// does for unknown transactions otherwise.
+    fn get_raw_transaction(
+        request: &serde_json::Value,
+        transactions: &[bitcoin::Transaction],
+    ) -> Reply {
+        let txid = request["params"][0].as_str().unwrap_or_default();
+        match transactions
+            .iter()
+            .find(|transaction| transaction.txid().to_string() == txid)
+        {
+            Some(transaction) => Reply::result(
+                request,
+                json!(bitcoin::consensus::encode::serialize_hex(transaction)),
+            ),
+            None => Reply::error(
+                request,
+                500,
+                -5,
+                "No such mempool or blockchain transaction",
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn lookup_unknown_deposit() {
+        let server =
+            test_server::serve(Duration::ZERO, |request| get_raw_transaction(request, &[])).await;
+        let drivechain = http_drivechain(&server);
+        let deposit = drivechain
+            .lookup_deposit(&bitcoin::OutPoint::null())
+            .await
+            .unwrap();
+        assert_eq!(deposit, None);
+    }
+
+    #[tokio::test]
+    async fn pending_deposit_left_mempool() {
+        let ctip = ctip_transaction(1000);
+        let ctip_txid = ctip.txid();
+        let server = test_server::serve(Duration::ZERO, move |request| {
+            match request["method"].as_str() {
+                Some("listsidechainctip") => Reply::result(
+                    request,
+                    json!({ "txid": ctip_txid, "vout": 0, "amount": 0.00001 }),
+                ),
+                // Evicted before it could be fetched.
+                Some("getrawmempool") => {
+                    Reply::result(request, json!([bitcoin::Txid::all_zeros()]))
+                }
+                _ => get_raw_transaction(request, std::slice::from_ref(&ctip)),
+            }
+        })
+        .await;
+        let drivechain = http_drivechain(&server);
+        let pending = drivechain.list_pending_deposits().await.unwrap();
+        assert!(pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn watch_deposits_skips_evicted_transactions() {
+        let ctip = ctip_transaction(1000);
+        let mut deposit = ctip_transaction(1500);
+        deposit.input.push(bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint {
+                txid: ctip.txid(),
+                vout: 0,
+            },
+            ..Default::default()
+        });
+        let address = bitcoin::script::PushBytesBuf::try_from(b"s0_address".to_vec()).unwrap();
+        deposit.output.push(bitcoin::TxOut {
+            value: 0,
+            script_pubkey: bitcoin::ScriptBuf::new_op_return(&address),
+        });
+        let (ctip_txid, deposit_txid) = (ctip.txid(), deposit.txid());
+        let transactions = [ctip, deposit.clone()];
+        let server = test_server::serve(Duration::ZERO, move |request| {
+            match request["method"].as_str() {
+                Some("listsidechainctip") => Reply::result(
+                    request,
+                    json!({ "txid": ctip_txid, "vout": 0, "amount": 0.00001 }),
+                ),
+                // The first transaction is evicted before it can be fetched.
+                Some("getrawmempool") => {
+                    Reply::result(request, json!([bitcoin::Txid::all_zeros(), deposit_txid]))
+                }
+                _ => get_raw_transaction(request, &transactions),
+            }
+        })
+        .await;
+        let drivechain = http_drivechain(&server);
+        let events = drivechain.watch_deposits(Duration::from_secs(60), Duration::from_secs(60));
+        futures::pin_mut!(events);
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!(
+            event,
+            DepositEvent::Pending {
+                outpoint: bitcoin::OutPoint {
+                    txid: deposit_txid,
+                    vout: 0,
+                },
+                output: Output {
+                    address: "s0_address".to_owned(),
+                    value: bitcoin::Amount::from_sat(500),
+                },
+                transaction: deposit,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn ctip_chain_from_unknown_ctip() {
+        let ctip = ctip_transaction(1000);
+        let ctip_txid = ctip.txid();
+        let server = test_server::serve(Duration::ZERO, move |request| {
+            match request["method"].as_str() {
+                Some("listsidechainctip") => Reply::result(
+                    request,
+                    json!({ "txid": ctip_txid, "vout": 0, "amount": 0.00001 }),
+                ),
+                _ => get_raw_transaction(request, std::slice::from_ref(&ctip)),
+            }
+        })
+        .await;
+        let drivechain = http_drivechain(&server);
+        let chain = drivechain.ctip_chain(None).await.unwrap();
+        assert_eq!(chain.len(), 1);
+        let unknown = bitcoin::OutPoint::null();
+        let result = drivechain.ctip_chain(Some(unknown)).await;
+        assert!(
+            matches!(result, Err(Error::CtipNotInChain { outpoint }) if outpoint == unknown),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn net_locked_value_without_ctip() {
+        let deposit = ctip_transaction(1000);
+        // Pays out 300, with a fee of 100.
+        let mut bundle = ctip_transaction(600);
+        bundle.input.push(bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint {
+                txid: deposit.txid(),
+                vout: 0,
+            },
+            ..Default::default()
+        });
+        bundle.output.push(bitcoin::TxOut {
+            value: 300,
+            script_pubkey: bitcoin::ScriptBuf::new(),
+        });
+        let bundle_txid = bundle.txid();
+        let block_hash = bitcoin::BlockHash::all_zeros();
+        let deposit_hex = bitcoin::consensus::encode::serialize_hex(&deposit);
+        let server = test_server::serve(Duration::ZERO, move |request| {
+            match request["method"].as_str() {
+                Some("listsidechainctip") => {
+                    Reply::error(request, 500, -1, "No CTIP found for sidechain!")
+                }
+                Some("getbestblockhash") => Reply::result(request, json!(block_hash)),
+                Some("listsidechaindepositsbyblock") => Reply::result(
+                    request,
+                    json!([{
+                        "hashblock": block_hash,
+                        "nburnindex": 0,
+                        "ntx": 1,
+                        "strdest": "s0_address",
+                        "txhex": deposit_hex,
+                    }]),
+                ),
+                Some("listspentwithdrawals") => Reply::result(
+                    request,
+                    json!([{ "nsidechain": 0, "hash": bundle_txid, "hashblock": block_hash }]),
+                ),
+                _ => get_raw_transaction(request, &[deposit.clone(), bundle.clone()]),
+            }
+        })
+        .await;
+        let drivechain = http_drivechain(&server);
+        let value = drivechain.net_locked_value().await.unwrap();
+        assert_eq!(value, bitcoin::Amount::from_sat(600));
+    }
+
+    #[tokio::test]
+    async fn generate_falls_back_to_generatetoaddress() {
+        let server = test_server::serve(
+            Duration::ZERO,
+            regtest_daemon(|request| match request["method"].as_str() {
+                Some("getnewaddress") => Reply::result(request, json!(REGTEST_ADDRESS)),
+                Some("generatetoaddress") => {
+                    Reply::result(request, json!([bitcoin::BlockHash::all_zeros()]))
+                }
+                _ => Reply::error(request, 404, -32601, "Method not found"),
+            }),
+        )
+        .await;
+        let drivechain = http_drivechain(&server);
+        let block_hashes = drivechain.generate(1).await.unwrap();
+        assert_eq!(block_hashes, [bitcoin::BlockHash::all_zeros()]);
+        assert_eq!(
+            *server.methods.lock().unwrap(),
+            [
+                "generate",
+                "getnewaddress",
+                "getblockchaininfo",
+                "generatetoaddress"
+            ]
+        );
+    }
+
+    #[cfg(feature = "sim")]
+    fn sim_drivechain() -> (SimChain, Drivechain<SimChain>) {
+        let sim = SimChain::new();
+        let drivechain = Drivechain::from_client(SIDECHAIN, sim.clone());
+        (sim, drivechain)
+    }
+
+    /// Deposits `a` of 1000 sat in the first block, and `b` of 500 sat in the
+    /// third, returning the three blocks.
+    #[cfg(feature = "sim")]
+    fn sim_two_deposits(sim: &SimChain) -> Vec<bitcoin::BlockHash> {
+        sim.add_deposit(SIDECHAIN, "a", bitcoin::Amount::from_sat(1000));
+        let mut blocks = sim.mine(2);
+        sim.add_deposit(SIDECHAIN, "b", bitcoin::Amount::from_sat(500));
+        blocks.extend(sim.mine(1));
+        blocks
+    }
+
+    #[cfg(feature = "sim")]
+    async fn deposit_values(
+        drivechain: &Drivechain<SimChain>,
+        start: Bound<bitcoin::BlockHash>,
+    ) -> Vec<(String, u64)> {
+        let tip = drivechain.get_mainchain_tip().await.unwrap();
+        drivechain
+            .get_deposits(tip, start)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|deposit| (deposit.output.address, deposit.output.value.to_sat()))
+            .collect()
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn deposits_included_start() {
+        let (sim, drivechain) = sim_drivechain();
+        let blocks = sim_two_deposits(&sim);
+        let b = vec![("b".to_owned(), 500)];
+        assert_eq!(
+            deposit_values(&drivechain, Bound::Included(blocks[2])).await,
+            b
+        );
+        assert_eq!(
+            deposit_values(&drivechain, Bound::Included(blocks[0])).await,
+            [vec![("a".to_owned(), 1000)], b].concat()
+        );
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn deposits_excluded_start() {
+        let (sim, drivechain) = sim_drivechain();
+        let blocks = sim_two_deposits(&sim);
+        let b = vec![("b".to_owned(), 500)];
+        // The start block has no deposits.
+        assert_eq!(
+            deposit_values(&drivechain, Bound::Excluded(blocks[1])).await,
+            b
+        );
+        // The start block has the spent CTIP.
+        assert_eq!(
+            deposit_values(&drivechain, Bound::Excluded(blocks[0])).await,
+            b
+        );
+        assert!(deposit_values(&drivechain, Bound::Excluded(blocks[2]))
+            .await
+            .is_empty());
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn deposits_after_start_with_two_deposits() {
+        let (sim, drivechain) = sim_drivechain();
+        sim.add_deposit(SIDECHAIN, "a", bitcoin::Amount::from_sat(1000));
+        sim.add_deposit(SIDECHAIN, "b", bitcoin::Amount::from_sat(300));
+        let start = sim.mine(1)[0];
+        sim.add_deposit(SIDECHAIN, "c", bitcoin::Amount::from_sat(500));
+        sim.mine(1);
+        assert_eq!(
+            deposit_values(&drivechain, Bound::Excluded(start)).await,
+            [("c".to_owned(), 500)]
+        );
+        assert_eq!(
+            deposit_values(&drivechain, Bound::Included(start)).await,
+            [
+                ("a".to_owned(), 1000),
+                ("b".to_owned(), 300),
+                ("c".to_owned(), 500)
+            ]
+        );
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn ctip_without_growth_is_not_a_deposit() {
+        let (sim, drivechain) = sim_drivechain();
+        sim.add_deposit(SIDECHAIN, "a", bitcoin::Amount::from_sat(1000));
+        sim.mine(1);
+        // Spends the CTIP, creating one with the same total.
+        sim.add_deposit(SIDECHAIN, "b", bitcoin::Amount::ZERO);
+        sim.mine(1);
+        assert_eq!(
+            deposit_values(&drivechain, Bound::Unbounded).await,
+            [("a".to_owned(), 1000)]
+        );
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn two_way_peg_data_per_block_from_start_without_deposits() {
+        let (sim, drivechain) = sim_drivechain();
+        let blocks = sim_two_deposits(&sim);
+        let per_block: Vec<_> = drivechain
+            .two_way_peg_data_per_block(blocks[2], Some(blocks[1]))
+            .try_collect()
+            .await
+            .unwrap();
+        let [(block_hash, two_way_peg_data)] = &per_block[..] else {
+            panic!("expected one block, got {per_block:?}");
+        };
+        assert_eq!(*block_hash, blocks[2]);
+        assert_eq!(two_way_peg_data.deposit_block_hash, Some(blocks[2]));
+        let values: Vec<_> = two_way_peg_data
+            .deposits
+            .values()
+            .map(|output| output.value.to_sat())
+            .collect();
+        assert_eq!(values, [500]);
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn follow_tip_reports_reorg() {
+        let (sim, drivechain) = sim_drivechain();
+        let old = sim.mine(3);
+        let events = drivechain.follow_tip(Duration::from_millis(1));
+        futures::pin_mut!(events);
+        // Records the starting tip, then waits to poll again.
+        assert!(futures::poll!(events.next()).is_pending());
+        let new = sim.reorg(2, 3);
+        let mut expected = vec![
+            TipEvent::Disconnected(old[2]),
+            TipEvent::Disconnected(old[1]),
+        ];
+        expected.extend(new.into_iter().map(TipEvent::Connected));
+        for expected in expected {
+            assert_eq!(events.next().await.unwrap().unwrap(), expected);
+        }
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn subscribe_deposits_seeds_from_earlier_deposit() {
+        let (sim, drivechain) = sim_drivechain();
+        sim.add_deposit(SIDECHAIN, "a", bitcoin::Amount::from_sat(1000));
+        sim.mine(1);
+        let updates = drivechain.subscribe_deposits(Duration::from_millis(1));
+        futures::pin_mut!(updates);
+        assert!(futures::poll!(updates.next()).is_pending());
+        sim.mine(1);
+        sim.add_deposit(SIDECHAIN, "b", bitcoin::Amount::from_sat(500));
+        let [block_b] = sim.mine(1)[..] else { panic!() };
+        let DepositUpdate::Deposit(deposit) = updates.next().await.unwrap().unwrap() else {
+            panic!("expected a deposit");
+        };
+        assert_eq!(deposit.block_hash, block_b);
+        assert_eq!(deposit.output.address, "b");
+        assert_eq!(deposit.output.value, bitcoin::Amount::from_sat(500));
+    }
+
+    #[cfg(feature = "sim")]
+    #[tokio::test]
+    async fn subscribe_deposits_rolls_back_on_reorg() {
+        let (sim, drivechain) = sim_drivechain();
+        let updates = drivechain.subscribe_deposits(Duration::from_millis(1));
+        futures::pin_mut!(updates);
+        assert!(futures::poll!(updates.next()).is_pending());
+        sim.add_deposit(SIDECHAIN, "a", bitcoin::Amount::from_sat(1000));
+        let [block_a] = sim.mine(1)[..] else { panic!() };
+        let DepositUpdate::Deposit(deposit) = updates.next().await.unwrap().unwrap() else {
+            panic!("expected a deposit");
+        };
+        assert_eq!(deposit.block_hash, block_a);
+        assert_eq!(deposit.output.value, bitcoin::Amount::from_sat(1000));
+        sim.add_deposit(SIDECHAIN, "b", bitcoin::Amount::from_sat(500));
+        let [block_b] = sim.reorg(1, 1)[..] else {
+            panic!()
+        };
+        assert_eq!(
+            updates.next().await.unwrap().unwrap(),
+            DepositUpdate::Rollback {
+                block_hash: block_a
+            }
+        );
+        let DepositUpdate::Deposit(deposit) = updates.next().await.unwrap().unwrap() else {
+            panic!("expected a deposit");
+        };
+        assert_eq!(deposit.block_hash, block_b);
+        assert_eq!(deposit.output.address, "b");
+        assert_eq!(deposit.output.value, bitcoin::Amount::from_sat(500));
+    }
 }
@@ -0,0 +1,887 @@
+//! JSON-RPC client used by [`crate::Drivechain`] to talk to the daemon.
+use base64::Engine as _;
+use futures::future::{BoxFuture, FutureExt as _, Shared};
+use jsonrpsee::core::client::{BatchResponse, ClientT};
+use jsonrpsee::core::http_helpers;
+use jsonrpsee::core::params::BatchRequestBuilder;
+use jsonrpsee::core::traits::ToRpcParams;
+use jsonrpsee::core::JsonRawValue;
+use jsonrpsee::http_client::transport;
+use jsonrpsee::http_client::{HeaderMap, HttpClient, HttpClientBuilder};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Names of RPC methods, for daemons that rename methods. Methods are
+/// identified by their default names, as used in [`crate::MainClient`].
+#[derive(Clone, Debug, Default)]
+pub struct MethodNames(HashMap<String, String>);
+
+impl MethodNames {
+    /// Call `name` instead of the method named `default`.
+    pub fn rename(mut self, default: &str, name: &str) -> Self {
+        self.0.insert(default.to_owned(), name.to_owned());
+        self
+    }
+
+    /// The name to call for the method named `default`.
+    pub fn get<'a>(&'a self, default: &'a str) -> &'a str {
+        self.0.get(default).map_or(default, String::as_str)
+    }
+}
+
+/// Params that have already been serialized.
+struct RawParams(Option<Box<JsonRawValue>>);
+
+impl ToRpcParams for RawParams {
+    fn to_rpc_params(self) -> Result<Option<Box<JsonRawValue>>, jsonrpsee::core::Error> {
+        Ok(self.0)
+    }
+}
+
+/// Middleware adapting the daemon's responses to what the HTTP client
+/// expects. The daemon includes both `result` and `error` in each response,
+/// with one of them null, and the client fails to parse `"error": null`.
+/// Before v28, the daemon also responds to failed calls with HTTP 404 or
+/// 500, and the client discards the body of any non-2xx response, so such
+/// responses are passed on as HTTP 200 for the error to reach the caller.
+#[derive(Clone, Debug)]
+struct DaemonResponseLayer {
+    max_response_size: u32,
+}
+
+impl<S> tower::Layer<S> for DaemonResponseLayer {
+    type Service = DaemonResponse<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DaemonResponse {
+            inner,
+            max_response_size: self.max_response_size,
+        }
+    }
+}
+
+/// Service added by [`DaemonResponseLayer`].
+#[derive(Clone, Debug)]
+struct DaemonResponse<S> {
+    inner: S,
+    max_response_size: u32,
+}
+
+/// A JSON-RPC response as sent by the daemon.
+#[derive(serde::Deserialize)]
+struct DaemonReply<'a> {
+    #[serde(borrow, default)]
+    result: Option<&'a JsonRawValue>,
+    #[serde(borrow, default)]
+    error: Option<&'a JsonRawValue>,
+    #[serde(borrow)]
+    id: &'a JsonRawValue,
+}
+
+impl DaemonReply<'_> {
+    /// The response with only one of `result` and `error`.
+    fn normalize(&self) -> String {
+        match self.error {
+            Some(error) => format!(r#"{{"error":{},"id":{}}}"#, error.get(), self.id.get()),
+            None => format!(
+                r#"{{"result":{},"id":{}}}"#,
+                self.result.map_or("null", |result| result.get()),
+                self.id.get()
+            ),
+        }
+    }
+}
+
+/// `body` with each response normalized, and whether any of them carries
+/// an error. `None` if `body` is not a JSON-RPC response.
+fn normalize_body(body: &[u8]) -> Option<(String, bool)> {
+    if let Ok(reply) = serde_json::from_slice::<DaemonReply>(body) {
+        return Some((reply.normalize(), reply.error.is_some()));
+    }
+    let replies = serde_json::from_slice::<Vec<DaemonReply>>(body).ok()?;
+    let normalized: Vec<String> = replies.iter().map(DaemonReply::normalize).collect();
+    let is_error = replies.iter().any(|reply| reply.error.is_some());
+    Some((format!("[{}]", normalized.join(",")), is_error))
+}
+
+impl<S> tower::Service<hyper::Request<hyper::Body>> for DaemonResponse<S>
+where
+    S: tower::Service<
+        hyper::Request<hyper::Body>,
+        Response = hyper::Response<hyper::Body>,
+        Error = transport::Error,
+    >,
+    S::Future: Send + 'static,
+{
+    type Response = hyper::Response<hyper::Body>;
+    type Error = transport::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: hyper::Request<hyper::Body>) -> Self::Future {
+        let response = self.inner.call(request);
+        let max_response_size = self.max_response_size;
+        async move {
+            let (mut parts, body) = response.await?.into_parts();
+            let body = match http_helpers::read_body(&parts.headers, body, max_response_size).await
+            {
+                Ok((body, _)) => body,
+                Err(_) if !parts.status.is_success() => {
+                    return Err(transport::Error::RequestFailure {
+                        status_code: parts.status.as_u16(),
+                    })
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let Some((body, is_error)) = normalize_body(&body) else {
+                return Ok(hyper::Response::from_parts(parts, body.into()));
+            };
+            if is_error {
+                parts.status = hyper::StatusCode::OK;
+            }
+            parts.headers.remove(hyper::header::CONTENT_LENGTH);
+            Ok(hyper::Response::from_parts(parts, body.into()))
+        }
+        .boxed()
+    }
+}
+
+/// A response with HTTP status 429 or 503, carried as a transport error so
+/// that the delay requested by its `Retry-After` header is not lost.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("rate limited with HTTP status {status_code}")]
+pub(crate) struct RateLimitedError {
+    pub status_code: u16,
+    pub retry_after: Option<Duration>,
+}
+
+/// The delay requested by a `Retry-After` header, given either in seconds or
+/// as an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Middleware failing responses with HTTP status 429 or 503 with a
+/// [`RateLimitedError`].
+#[derive(Clone, Debug)]
+struct RateLimitLayer;
+
+impl<S> tower::Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit { inner }
+    }
+}
+
+/// Service added by [`RateLimitLayer`].
+#[derive(Clone, Debug)]
+struct RateLimit<S> {
+    inner: S,
+}
+
+impl<S> tower::Service<hyper::Request<hyper::Body>> for RateLimit<S>
+where
+    S: tower::Service<
+        hyper::Request<hyper::Body>,
+        Response = hyper::Response<hyper::Body>,
+        Error = transport::Error,
+    >,
+    S::Future: Send + 'static,
+{
+    type Response = hyper::Response<hyper::Body>;
+    type Error = transport::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: hyper::Request<hyper::Body>) -> Self::Future {
+        let response = self.inner.call(request);
+        async move {
+            let response = response.await?;
+            let status_code = response.status().as_u16();
+            if !matches!(status_code, 429 | 503) {
+                return Ok(response);
+            }
+            let retry_after = response
+                .headers()
+                .get(hyper::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            Err(transport::Error::Http(Box::new(RateLimitedError {
+                status_code,
+                retry_after,
+            })))
+        }
+        .boxed()
+    }
+}
+
+type DaemonHttpClient = HttpClient<DaemonResponse<RateLimit<transport::HttpBackend>>>;
+
+/// Settings for the HTTP client, kept so that it can be rebuilt with new
+/// credentials.
+#[derive(Clone, Debug)]
+pub(crate) struct HttpSettings {
+    pub url: String,
+    pub max_response_size: u32,
+    pub request_timeout: Duration,
+    /// Time to wait for a TCP connection when checking that the host is
+    /// reachable. The HTTP client has no connect timeout of its own.
+    pub connect_timeout: Duration,
+    pub rate_limit_retries: u32,
+    pub rate_limit_backoff: Duration,
+    /// Budget in bytes for cached immutable block responses. 0 disables the
+    /// cache.
+    pub block_cache_size: usize,
+    /// Coalesce identical concurrent requests.
+    pub singleflight: bool,
+}
+
+impl HttpSettings {
+    fn build(&self, user: &str, password: &str) -> Result<DaemonHttpClient, crate::Error> {
+        let mut headers = HeaderMap::new();
+        let auth = format!("{user}:{password}");
+        let header_value = format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD_NO_PAD.encode(auth)
+        )
+        .parse()?;
+        headers.insert("authorization", header_value);
+        Ok(HttpClientBuilder::default()
+            .set_headers(headers)
+            .max_response_size(self.max_response_size)
+            .request_timeout(self.request_timeout)
+            .set_middleware(
+                tower::ServiceBuilder::new()
+                    .layer(DaemonResponseLayer {
+                        max_response_size: self.max_response_size,
+                    })
+                    .layer(RateLimitLayer),
+            )
+            .build(&self.url)?)
+    }
+}
+
+/// The HTTP status code, if the server responded with an error status.
+pub(crate) fn http_status(err: &jsonrpsee::core::Error) -> Option<u16> {
+    let jsonrpsee::core::Error::Transport(err) = err else {
+        return None;
+    };
+    match err.downcast_ref::<transport::Error>() {
+        Some(transport::Error::RequestFailure { status_code }) => Some(*status_code),
+        _ => None,
+    }
+}
+
+/// The rate limiting response, if the server responded with HTTP 429 or
+/// 503.
+pub(crate) fn rate_limited(err: &jsonrpsee::core::Error) -> Option<&RateLimitedError> {
+    let jsonrpsee::core::Error::Transport(err) = err else {
+        return None;
+    };
+    match err.downcast_ref::<transport::Error>() {
+        Some(transport::Error::Http(err)) => err.downcast_ref(),
+        _ => None,
+    }
+}
+
+/// Longest raw response kept when a response cannot be parsed, in bytes.
+const MAX_RAW_RESPONSE_LEN: usize = 1024;
+
+/// A response that could not be parsed as the type expected for `method`.
+/// `jsonrpsee` errors have no variant for this, so it is carried as a
+/// transport error and turned into [`crate::Error::ResponseParse`].
+#[derive(Debug, thiserror::Error)]
+#[error("could not parse response to {method}")]
+pub(crate) struct ResponseParseError {
+    pub method: String,
+    pub raw: String,
+    #[source]
+    pub source: serde_json::Error,
+}
+
+fn parse_response<R>(method: &str, response: &JsonRawValue) -> Result<R, jsonrpsee::core::Error>
+where
+    R: DeserializeOwned,
+{
+    serde_json::from_str(response.get()).map_err(|source| {
+        let raw = response.get();
+        let mut len = raw.len().min(MAX_RAW_RESPONSE_LEN);
+        while !raw.is_char_boundary(len) {
+            len -= 1;
+        }
+        let err = ResponseParseError {
+            method: method.to_owned(),
+            raw: raw[..len].to_owned(),
+            source,
+        };
+        jsonrpsee::core::Error::Transport(err.into())
+    })
+}
+
+/// A copy of `err`, for sharing an error between coalesced requests.
+/// `jsonrpsee` errors cannot be cloned, so transport errors other than HTTP
+/// error statuses are copied as their message.
+fn copy_error(err: &jsonrpsee::core::Error) -> jsonrpsee::core::Error {
+    use jsonrpsee::core::Error;
+    if let Some(status_code) = http_status(err) {
+        return Error::Transport(transport::Error::RequestFailure { status_code }.into());
+    }
+    if let Some(err) = rate_limited(err) {
+        return Error::Transport(transport::Error::Http(Box::new(err.clone())).into());
+    }
+    match err {
+        Error::Call(err) => Error::Call(err.clone()),
+        Error::RequestTimeout => Error::RequestTimeout,
+        err => Error::Custom(err.to_string()),
+    }
+}
+
+/// Whether calls to `method`, by its default name, only read from the
+/// daemon. Only these are coalesced by singleflight, as sharing the response
+/// to a call such as `getnewaddress` or `generate` would hand every caller
+/// the same result while the daemon acts once.
+fn is_read_only(method: &str) -> bool {
+    matches!(
+        method,
+        "getblock"
+            | "getblockhash"
+            | "getblockheader"
+            | "getbestblockhash"
+            | "getblockcount"
+            | "getblockchaininfo"
+            | "getrawtransaction"
+    ) || method.starts_with("list")
+}
+
+type SharedResponse =
+    Shared<BoxFuture<'static, Result<Box<JsonRawValue>, Arc<jsonrpsee::core::Error>>>>;
+
+/// Requests in flight, keyed by method and params.
+type InFlight = Arc<Mutex<HashMap<String, SharedResponse>>>;
+
+/// Responses for immutable block data, evicting the least recently used
+/// once their total size exceeds the budget.
+#[derive(Debug)]
+struct BlockCache {
+    /// Budget for the total size of keys and responses, in bytes.
+    capacity: usize,
+    size: usize,
+    /// Responses, with the tick at which each was last used.
+    entries: HashMap<String, (u64, Arc<JsonRawValue>)>,
+    /// Keys by the tick at which they were last used, least recent first.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+fn cache_entry_size(key: &str, value: &JsonRawValue) -> usize {
+    key.len() + value.get().len()
+}
+
+impl BlockCache {
+    fn new(capacity: usize) -> Self {
+        BlockCache {
+            capacity,
+            size: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<JsonRawValue>> {
+        let (last_used, value) = self.entries.get_mut(key)?;
+        let key = self.order.remove(last_used).expect("entries are ordered");
+        self.tick += 1;
+        *last_used = self.tick;
+        self.order.insert(self.tick, key);
+        Some(value.clone())
+    }
+
+    /// Responses larger than the budget are not cached.
+    fn insert(&mut self, key: String, value: Arc<JsonRawValue>) {
+        let size = cache_entry_size(&key, &value);
+        if size > self.capacity {
+            return;
+        }
+        if let Some((last_used, old)) = self.entries.remove(&key) {
+            self.order.remove(&last_used);
+            self.size -= cache_entry_size(&key, &old);
+        }
+        while self.size + size > self.capacity {
+            let Some((_, evicted)) = self.order.pop_first() else {
+                break;
+            };
+            let (_, value) = self
+                .entries
+                .remove(&evicted)
+                .expect("ordered keys are entries");
+            self.size -= cache_entry_size(&evicted, &value);
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (self.tick, value));
+        self.size += size;
+    }
+}
+
+/// The cache key for a request, if its response is immutable. The raw
+/// `getblock` and `getblockheader` responses for a block hash never change,
+/// even across reorgs. The verbose responses are not cached, as they include
+/// the block's position relative to the tip.
+fn block_cache_key(method: &str, params: Option<&JsonRawValue>) -> Option<String> {
+    let params: Vec<serde_json::Value> = serde_json::from_str(params?.get()).ok()?;
+    let immutable = match (method, params.as_slice()) {
+        ("getblock", [_, verbosity]) => verbosity.as_u64() == Some(0),
+        ("getblockheader", [_, verbose]) => verbose.as_bool() == Some(false),
+        _ => false,
+    };
+    immutable.then(|| format!("{method} {}", params[0]))
+}
+
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    settings: HttpSettings,
+    user: String,
+    inner: DaemonHttpClient,
+    method_names: Arc<MethodNames>,
+    block_cache: Option<Arc<Mutex<BlockCache>>>,
+    in_flight: Option<InFlight>,
+}
+
+impl RpcClient {
+    pub(crate) fn new(
+        settings: HttpSettings,
+        user: &str,
+        password: &str,
+    ) -> Result<Self, crate::Error> {
+        let inner = settings.build(user, password)?;
+        let block_cache = (settings.block_cache_size > 0)
+            .then(|| Arc::new(Mutex::new(BlockCache::new(settings.block_cache_size))));
+        let in_flight = settings.singleflight.then(InFlight::default);
+        Ok(RpcClient {
+            settings,
+            block_cache,
+            in_flight,
+            user: user.to_owned(),
+            inner,
+            method_names: Arc::new(MethodNames::default()),
+        })
+    }
+
+    /// Rebuild the HTTP client with new credentials.
+    pub(crate) fn update_credentials(
+        &mut self,
+        user: &str,
+        password: &str,
+    ) -> Result<(), crate::Error> {
+        self.inner = self.settings.build(user, password)?;
+        self.user = user.to_owned();
+        Ok(())
+    }
+
+    pub(crate) fn settings(&self) -> &HttpSettings {
+        &self.settings
+    }
+
+    pub(crate) fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// Run `call`, retrying while the server is rate limiting.
+    async fn with_retries<T, F, Fut>(&self, mut call: F) -> Result<T, jsonrpsee::core::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, jsonrpsee::core::Error>>,
+    {
+        let mut backoff = self.settings.rate_limit_backoff;
+        for _ in 0..self.settings.rate_limit_retries {
+            let retry_after = match call().await {
+                Err(err) => match rate_limited(&err) {
+                    Some(err) => err.retry_after.unwrap_or_default(),
+                    None => return Err(err),
+                },
+                result => return result,
+            };
+            tokio::time::sleep(backoff.max(retry_after)).await;
+            backoff *= 2;
+        }
+        call().await
+    }
+
+    /// Send a request to `method`, as renamed. If singleflight is enabled
+    /// and `read_only` is set, the response is shared with identical
+    /// requests already in flight.
+    async fn request_raw(
+        &self,
+        method: &str,
+        read_only: bool,
+        params: Option<Box<JsonRawValue>>,
+    ) -> Result<Box<JsonRawValue>, jsonrpsee::core::Error> {
+        let in_flight = self.in_flight.as_ref().filter(|_| read_only);
+        let Some(in_flight) = in_flight else {
+            return self
+                .with_retries(|| self.inner.request(method, RawParams(params.clone())))
+                .await;
+        };
+        let key = match &params {
+            Some(params) => format!("{method} {}", params.get()),
+            None => method.to_owned(),
+        };
+        let response = {
+            let mut in_flight = in_flight.lock().expect("in flight mutex poisoned");
+            in_flight
+                .entry(key.clone())
+                .or_insert_with(|| {
+                    let client = self.clone();
+                    let method = method.to_owned();
+                    async move {
+                        client
+                            .with_retries(|| {
+                                client.inner.request(&method, RawParams(params.clone()))
+                            })
+                            .await
+                            .map_err(Arc::new)
+                    }
+                    .boxed()
+                    .shared()
+                })
+                .clone()
+        };
+        let result = response.clone().await;
+        // Later requests must not be given this response.
+        let mut in_flight = in_flight.lock().expect("in flight mutex poisoned");
+        if in_flight
+            .get(&key)
+            .is_some_and(|entry| entry.ptr_eq(&response))
+        {
+            in_flight.remove(&key);
+        }
+        result.map_err(|err| copy_error(&err))
+    }
+
+    pub fn method_names(&self) -> &MethodNames {
+        &self.method_names
+    }
+
+    pub fn set_method_names(&mut self, method_names: MethodNames) {
+        self.method_names = Arc::new(method_names);
+    }
+}
+
+#[async_trait::async_trait]
+impl ClientT for RpcClient {
+    async fn notification<Params>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<(), jsonrpsee::core::Error>
+    where
+        Params: ToRpcParams + Send,
+    {
+        self.inner
+            .notification(self.method_names.get(method), params)
+            .await
+    }
+
+    async fn request<R, Params>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<R, jsonrpsee::core::Error>
+    where
+        R: DeserializeOwned,
+        Params: ToRpcParams + Send,
+    {
+        let params = params.to_rpc_params()?;
+        let cache_key = match &self.block_cache {
+            Some(_) => block_cache_key(method, params.as_deref()),
+            None => None,
+        };
+        let read_only = is_read_only(method);
+        let method = self.method_names.get(method);
+        let (Some(block_cache), Some(cache_key)) = (&self.block_cache, cache_key) else {
+            let response = self.request_raw(method, read_only, params).await?;
+            return parse_response(method, &response);
+        };
+        let cached = block_cache
+            .lock()
+            .expect("block cache mutex poisoned")
+            .get(&cache_key);
+        let response = match cached {
+            Some(response) => response,
+            None => {
+                let response: Arc<JsonRawValue> =
+                    self.request_raw(method, read_only, params).await?.into();
+                block_cache
+                    .lock()
+                    .expect("block cache mutex poisoned")
+                    .insert(cache_key, response.clone());
+                response
+            }
+        };
+        parse_response(method, &response)
+    }
+
+    async fn batch_request<'a, R>(
+        &self,
+        batch: BatchRequestBuilder<'a>,
+    ) -> Result<BatchResponse<'a, R>, jsonrpsee::core::Error>
+    where
+        R: DeserializeOwned + std::fmt::Debug + 'a,
+    {
+        let batch = batch.build()?;
+        let response = self
+            .with_retries(|| async {
+                let mut renamed = BatchRequestBuilder::new();
+                for (method, params) in &batch {
+                    renamed.insert(self.method_names.get(method), RawParams(params.clone()))?;
+                }
+                self.inner.batch_request::<Box<JsonRawValue>>(renamed).await
+            })
+            .await?;
+        let successful = response.num_successful_calls();
+        let failed = response.num_failed_calls();
+        let entries = batch
+            .iter()
+            .zip(response)
+            .map(|((method, _), entry)| match entry {
+                Ok(raw) => parse_response(method, &raw).map(Ok),
+                Err(err) => Ok(Err(err.into_owned())),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(BatchResponse::new(successful, entries, failed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Reply};
+    use jsonrpsee::rpc_params;
+    use serde_json::json;
+
+    fn settings(host: &str) -> HttpSettings {
+        HttpSettings {
+            url: format!("http://{host}"),
+            max_response_size: 1024 * 1024,
+            request_timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(5),
+            rate_limit_retries: 0,
+            rate_limit_backoff: Duration::from_millis(10),
+            block_cache_size: 0,
+            singleflight: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn error_status_is_a_call_error() {
+        let server =
+            test_server::serve(Duration::ZERO, |request| match request["method"].as_str() {
+                Some("getblockcount") => Reply::result(request, json!(7)),
+                Some("getrawtransaction") => Reply::error(
+                    request,
+                    500,
+                    -5,
+                    "No such mempool or blockchain transaction",
+                ),
+                _ => Reply::error(request, 404, -32601, "Method not found"),
+            })
+            .await;
+        let client = RpcClient::new(settings(&server.host), "user", "password").unwrap();
+        let count: u64 = client
+            .request("getblockcount", rpc_params![])
+            .await
+            .unwrap();
+        assert_eq!(count, 7);
+        for (method, code) in [("getrawtransaction", -5), ("generate", -32601)] {
+            match client.request::<String, _>(method, rpc_params![]).await {
+                Err(jsonrpsee::core::Error::Call(err)) => assert_eq!(err.code(), code),
+                result => panic!("unexpected result for {method}: {result:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn singleflight_coalesces_only_reads() {
+        let server = test_server::serve(Duration::from_millis(100), |request| {
+            match request["method"].as_str() {
+                Some("getblockcount") => Reply::result(request, json!(7)),
+                _ => Reply::result(request, json!("address")),
+            }
+        })
+        .await;
+        let settings = HttpSettings {
+            singleflight: true,
+            ..settings(&server.host)
+        };
+        let client = RpcClient::new(settings, "user", "password").unwrap();
+        let count = || client.request::<u64, _>("getblockcount", rpc_params![]);
+        let (a, b) = futures::join!(count(), count());
+        assert_eq!((a.unwrap(), b.unwrap()), (7, 7));
+        let address = || client.request::<String, _>("getnewaddress", rpc_params!["", "bech32"]);
+        let (a, b) = futures::join!(address(), address());
+        a.unwrap();
+        b.unwrap();
+        assert_eq!(
+            *server.methods.lock().unwrap(),
+            ["getblockcount", "getnewaddress", "getnewaddress"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limited_with_retry_after() {
+        let server = test_server::serve(Duration::ZERO, |_| {
+            Reply::status(429).header("retry-after", "120")
+        })
+        .await;
+        let client = RpcClient::new(settings(&server.host), "user", "password").unwrap();
+        let err = client
+            .request::<u64, _>("getblockcount", rpc_params![])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            crate::Error::from(err),
+            crate::Error::RateLimited {
+                retry_after: Some(retry_after)
+            } if retry_after == Duration::from_secs(120)
+        ));
+    }
+
+    #[tokio::test]
+    async fn retry_waits_for_retry_after() {
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        let server = test_server::serve(Duration::ZERO, move |request| {
+            if calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                Reply::status(503).header("retry-after", "1")
+            } else {
+                Reply::result(request, json!(7))
+            }
+        })
+        .await;
+        let settings = HttpSettings {
+            rate_limit_retries: 1,
+            ..settings(&server.host)
+        };
+        let client = RpcClient::new(settings, "user", "password").unwrap();
+        let start = std::time::Instant::now();
+        let count: u64 = client
+            .request("getblockcount", rpc_params![])
+            .await
+            .unwrap();
+        assert_eq!(count, 7);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retry_after_formats() {
+        assert_eq!(parse_retry_after("5"), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[tokio::test]
+    async fn error_status_without_rpc_error_is_kept() {
+        let server = test_server::serve(Duration::ZERO, |_| Reply::status(401)).await;
+        let client = RpcClient::new(settings(&server.host), "user", "password").unwrap();
+        let err = client
+            .request::<u64, _>("getblockcount", rpc_params![])
+            .await
+            .unwrap_err();
+        assert_eq!(http_status(&err), Some(401));
+    }
+
+    fn params(params: serde_json::Value) -> Box<JsonRawValue> {
+        JsonRawValue::from_string(params.to_string()).unwrap()
+    }
+
+    #[test]
+    fn only_raw_block_responses_are_cached() {
+        let hash = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+        let key = |method, p| block_cache_key(method, Some(&params(p)));
+        assert!(key("getblock", serde_json::json!([hash, 0])).is_some());
+        assert!(key("getblockheader", serde_json::json!([hash, false])).is_some());
+        assert_eq!(key("getblock", serde_json::json!([hash, 1])), None);
+        assert_eq!(key("getblock", serde_json::json!([hash, null])), None);
+        assert_eq!(key("getblockheader", serde_json::json!([hash, true])), None);
+        assert_ne!(
+            key("getblock", serde_json::json!([hash, 0])),
+            key("getblockheader", serde_json::json!([hash, false]))
+        );
+    }
+
+    #[test]
+    fn block_cache_evicts_least_recently_used() {
+        // Room for two entries of 5 bytes.
+        let mut cache = BlockCache::new(10);
+        let value = || Arc::from(params(serde_json::json!("00")));
+        cache.insert("a".to_owned(), value());
+        cache.insert("b".to_owned(), value());
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_owned(), value());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn block_cache_stays_within_budget() {
+        let mut cache = BlockCache::new(10);
+        let value = |len| Arc::from(params(serde_json::json!("0".repeat(len))));
+        cache.insert("a".to_owned(), value(2));
+        cache.insert("b".to_owned(), value(2));
+        // Too large to cache at all.
+        cache.insert("c".to_owned(), value(9));
+        assert!(cache.get("c").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_some());
+        // Evicts both smaller entries.
+        cache.insert("d".to_owned(), value(6));
+        assert_eq!(cache.size, 9);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("d").is_some());
+        // Replacing an entry frees its old size.
+        cache.insert("d".to_owned(), value(2));
+        assert_eq!(cache.size, 5);
+    }
+
+    #[test]
+    fn parse_failure_is_a_response_parse_error() {
+        let raw = params(serde_json::json!({ "address": "x".repeat(2 * MAX_RAW_RESPONSE_LEN) }));
+        let err = parse_response::<String>("getnewaddress", &raw).unwrap_err();
+        match crate::Error::from(err) {
+            crate::Error::ResponseParse { method, raw, .. } => {
+                assert_eq!(method, "getnewaddress");
+                assert_eq!(raw.len(), MAX_RAW_RESPONSE_LEN);
+            }
+            err => panic!("unexpected error: {err:?}"),
+        }
+    }
+}
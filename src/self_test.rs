@@ -0,0 +1,213 @@
+//! End to end check of a regtest daemon, exercising the two way peg.
+use crate::{Drivechain, Error, MainClient as _, WithdrawalBundleStatus};
+use jsonrpsee::core::client::ClientT;
+
+/// Address that self test deposits are made to.
+const SELF_TEST_ADDRESS: &str = "bip300301_self_test";
+const SELF_TEST_DEPOSIT: bitcoin::Amount = bitcoin::Amount::from_sat(1_000_000);
+const SELF_TEST_DEPOSIT_FEE: bitcoin::Amount = bitcoin::Amount::from_sat(10_000);
+
+/// A deposit made by [`Drivechain::regtest_self_test`].
+struct SelfTestDeposit {
+    /// Tips before and after the deposit was mined.
+    start: bitcoin::BlockHash,
+    end: bitcoin::BlockHash,
+    /// Increase of the CTIP.
+    value: bitcoin::Amount,
+}
+
+/// Outcome of one step of [`Drivechain::regtest_self_test`].
+#[derive(Debug)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub result: Result<(), Error>,
+}
+
+/// Outcomes of the steps of [`Drivechain::regtest_self_test`], in order.
+/// Steps after a failed step are not run.
+#[derive(Debug, Default)]
+pub struct SelfTestReport {
+    pub steps: Vec<SelfTestStep>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|step| step.result.is_ok())
+    }
+
+    fn record<T>(&mut self, name: &'static str, result: Result<T, Error>) -> Option<T> {
+        let (result, value) = match result {
+            Ok(value) => (Ok(()), Some(value)),
+            Err(err) => (Err(err), None),
+        };
+        self.steps.push(SelfTestStep { name, result });
+        value
+    }
+}
+
+impl<C> Drivechain<C>
+where
+    C: ClientT + Send + Sync,
+{
+    /// Run the two way peg on a regtest daemon: activate this sidechain if
+    /// needed, deposit to it, check that the deposit is reported, then
+    /// broadcast a withdrawal bundle and check that it is pending.
+    ///
+    /// This mines blocks and spends from the daemon's wallet, so it must only
+    /// be used against a disposable regtest node. Fails if the daemon is not
+    /// on regtest; failures of individual steps are in the report.
+    pub async fn regtest_self_test(&self) -> Result<SelfTestReport, Error> {
+        let network = self.network().await?;
+        if network != bitcoin::Network::Regtest {
+            return Err(Error::NotRegtest { network });
+        }
+        let mut report = SelfTestReport::default();
+        let activated = self.self_test_activate().await;
+        if report.record("activate sidechain", activated).is_none() {
+            return Ok(report);
+        }
+        let deposited = self.self_test_deposit().await;
+        let Some(deposit) = report.record("deposit", deposited) else {
+            return Ok(report);
+        };
+        let reported = self.self_test_check_deposit(deposit).await;
+        if report.record("two way peg data", reported).is_none() {
+            return Ok(report);
+        }
+        let broadcast = self.self_test_broadcast_bundle().await;
+        let Some(txid) = report.record("broadcast withdrawal bundle", broadcast) else {
+            return Ok(report);
+        };
+        let status = self.self_test_check_bundle(txid).await;
+        report.record("withdrawal bundle status", status);
+        Ok(report)
+    }
+
+    async fn self_test_is_active(&self) -> Result<bool, Error> {
+        let slot = self.sidechain_number;
+        Ok(self
+            .list_active_sidechains()
+            .await?
+            .iter()
+            .any(|sidechain| sidechain.nsidechain == slot))
+    }
+
+    async fn self_test_activate(&self) -> Result<(), Error> {
+        if self.self_test_is_active().await? {
+            return Ok(());
+        }
+        self.client
+            .createsidechainproposal(self.sidechain_number, "self test", "bip300301 self test")
+            .await?;
+        // Blocks mined by the daemon ack its own proposals.
+        const BATCH: u32 = 100;
+        for _ in 0..=crate::SIDECHAIN_ACTIVATION_MAX_AGE / BATCH + 1 {
+            self.generate(BATCH).await?;
+            if self.self_test_is_active().await? {
+                return Ok(());
+            }
+        }
+        Err(Error::SelfTestCheck {
+            check: "sidechain activated",
+        })
+    }
+
+    /// The CTIP amount, or zero if the sidechain has no CTIP yet.
+    async fn self_test_ctip_amount(&self) -> Result<bitcoin::Amount, Error> {
+        match self.get_ctip().await {
+            Ok(ctip) => Ok(ctip.amount),
+            Err(Error::Jsonrpsee(jsonrpsee::core::Error::Call(_))) => Ok(bitcoin::Amount::ZERO),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn self_test_deposit(&self) -> Result<SelfTestDeposit, Error> {
+        let start = self.get_mainchain_tip().await?;
+        let ctip_before = self.self_test_ctip_amount().await?;
+        self.create_deposit(
+            SELF_TEST_ADDRESS,
+            SELF_TEST_DEPOSIT,
+            Some(SELF_TEST_DEPOSIT_FEE),
+        )
+        .await?;
+        self.generate(1).await?;
+        let end = self.get_mainchain_tip().await?;
+        let ctip_after = self.self_test_ctip_amount().await?;
+        let value = ctip_after
+            .checked_sub(ctip_before)
+            .ok_or(Error::SelfTestCheck {
+                check: "deposit increased ctip",
+            })?;
+        Ok(SelfTestDeposit { start, end, value })
+    }
+
+    async fn self_test_check_deposit(&self, deposit: SelfTestDeposit) -> Result<(), Error> {
+        let two_way_peg_data = self
+            .get_two_way_peg_data(deposit.end, Some(deposit.start))
+            .await?;
+        let found = two_way_peg_data
+            .deposits
+            .values()
+            .any(|output| output.address == SELF_TEST_ADDRESS && output.value == deposit.value);
+        if !found {
+            return Err(Error::SelfTestCheck {
+                check: "deposit reported",
+            });
+        }
+        Ok(())
+    }
+
+    /// Broadcast a bundle that spends the CTIP back to itself.
+    async fn self_test_broadcast_bundle(&self) -> Result<bitcoin::Txid, Error> {
+        let (ctip, ctip_transaction) = self.get_ctip_transaction().await?;
+        let script_pubkey = crate::ctip_script(&ctip, &ctip_transaction)?.to_owned();
+        let bundle = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: ctip.outpoint(),
+                script_sig: bitcoin::ScriptBuf::new(),
+                sequence: bitcoin::Sequence::MAX,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![bitcoin::TxOut {
+                value: ctip.amount.to_sat(),
+                script_pubkey,
+            }],
+        };
+        let txid = bundle.txid();
+        self.broadcast_withdrawal_bundle(bundle).await?;
+        Ok(txid)
+    }
+
+    async fn self_test_check_bundle(&self, txid: bitcoin::Txid) -> Result<(), Error> {
+        self.generate(1).await?;
+        let bundle_statuses = self.get_withdrawal_bundle_statuses().await?;
+        match bundle_statuses.statuses.get(&txid) {
+            Some(WithdrawalBundleStatus::Pending { .. }) => Ok(()),
+            _ => Err(Error::SelfTestCheck {
+                check: "withdrawal bundle pending",
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_server::{self, Reply};
+    use crate::{DrivechainBuilder, SidechainId};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn ctip_amount_before_first_deposit() {
+        let server = test_server::serve(Duration::ZERO, |request| {
+            Reply::error(request, 500, -1, "No CTIP found for sidechain!")
+        })
+        .await;
+        let drivechain = DrivechainBuilder::new(SidechainId(0), &server.host, "user", "password")
+            .build()
+            .unwrap();
+        let amount = drivechain.self_test_ctip_amount().await.unwrap();
+        assert_eq!(amount, bitcoin::Amount::ZERO);
+    }
+}
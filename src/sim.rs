@@ -0,0 +1,467 @@
+//! In-memory simulated mainchain.
+//!
+//! [`SimChain`] implements [`ClientT`] by answering a subset of the mainchain
+//! RPC methods from a mutable in-memory chain, so it can be used in place of
+//! a real daemon with [`crate::Drivechain`].
+use crate::client::{Block, Deposit, SidechainId, TransactionInfo};
+use bitcoin::blockdata::{opcodes, script};
+use bitcoin::consensus::Encodable;
+use bitcoin::hashes::Hash as _;
+use jsonrpsee::core::client::{BatchResponse, ClientT};
+use jsonrpsee::core::params::BatchRequestBuilder;
+use jsonrpsee::core::traits::ToRpcParams;
+use jsonrpsee::types::error::{ErrorCode, ErrorObject};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+struct SimDeposit {
+    nsidechain: u8,
+    address: String,
+    amount: bitcoin::Amount,
+}
+
+struct SimBlock {
+    block: bitcoin::Block,
+    height: usize,
+    // Deposits in this block, as (nsidechain, address, index into txdata).
+    deposits: Vec<(u8, String, usize)>,
+}
+
+struct State {
+    blocks: HashMap<bitcoin::BlockHash, SimBlock>,
+    // Active chain, indexed by height.
+    active: Vec<bitcoin::BlockHash>,
+    mempool: Vec<SimDeposit>,
+    // Incremented for every mined block, so that blocks mined at the same
+    // height on different branches have distinct hashes.
+    nonce: u32,
+}
+
+/// A simulated mainchain, shared between clones.
+#[derive(Clone)]
+pub struct SimChain(Arc<Mutex<State>>);
+
+/// Script of the output that holds a sidechain's CTIP.
+fn ctip_script(nsidechain: u8) -> bitcoin::ScriptBuf {
+    script::Builder::new()
+        .push_opcode(opcodes::all::OP_NOP5)
+        .push_slice([nsidechain])
+        .push_opcode(opcodes::OP_TRUE)
+        .into_script()
+}
+
+impl State {
+    fn tip(&self) -> bitcoin::BlockHash {
+        *self
+            .active
+            .last()
+            .expect("chain always has a genesis block")
+    }
+
+    fn is_active(&self, hash: &bitcoin::BlockHash) -> bool {
+        self.blocks
+            .get(hash)
+            .is_some_and(|block| self.active.get(block.height) == Some(hash))
+    }
+
+    /// Outpoint and value of the CTIP for a sidechain on the active chain.
+    fn ctip(&self, nsidechain: u8) -> Option<(bitcoin::OutPoint, u64)> {
+        let script = ctip_script(nsidechain);
+        self.active.iter().rev().find_map(|hash| {
+            let block = &self.blocks[hash].block;
+            block.txdata.iter().rev().find_map(|tx| {
+                let vout = tx.output.iter().position(|o| o.script_pubkey == script)?;
+                let outpoint = bitcoin::OutPoint {
+                    txid: tx.txid(),
+                    vout: vout as u32,
+                };
+                Some((outpoint, tx.output[vout].value))
+            })
+        })
+    }
+
+    fn mine_one(&mut self) -> bitcoin::BlockHash {
+        let (prev_blockhash, height) = match self.active.last() {
+            Some(tip) => (*tip, self.active.len()),
+            None => (bitcoin::BlockHash::all_zeros(), 0),
+        };
+        self.nonce += 1;
+        let coinbase = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::null(),
+                script_sig: script::Builder::new()
+                    .push_int(height as i64)
+                    .push_int(self.nonce as i64)
+                    .into_script(),
+                sequence: bitcoin::Sequence::MAX,
+                witness: bitcoin::Witness::new(),
+            }],
+            output: vec![bitcoin::TxOut {
+                value: 0,
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+        let mut txdata = vec![coinbase];
+        let mut deposits = Vec::new();
+        let mut ctips = HashMap::new();
+        for deposit in std::mem::take(&mut self.mempool) {
+            let (previous_output, total) = *ctips
+                .entry(deposit.nsidechain)
+                .or_insert_with(|| self.ctip(deposit.nsidechain).unwrap_or_default());
+            let dest: &bitcoin::script::PushBytes = deposit
+                .address
+                .as_bytes()
+                .try_into()
+                .expect("deposit address is too long");
+            let tx = bitcoin::Transaction {
+                version: 2,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: vec![bitcoin::TxIn {
+                    previous_output,
+                    script_sig: bitcoin::ScriptBuf::new(),
+                    sequence: bitcoin::Sequence::MAX,
+                    witness: bitcoin::Witness::new(),
+                }],
+                output: vec![
+                    bitcoin::TxOut {
+                        value: total + deposit.amount.to_sat(),
+                        script_pubkey: ctip_script(deposit.nsidechain),
+                    },
+                    bitcoin::TxOut {
+                        value: 0,
+                        script_pubkey: bitcoin::ScriptBuf::new_op_return(&dest),
+                    },
+                ],
+            };
+            let outpoint = bitcoin::OutPoint {
+                txid: tx.txid(),
+                vout: 0,
+            };
+            ctips.insert(
+                deposit.nsidechain,
+                (outpoint, total + deposit.amount.to_sat()),
+            );
+            deposits.push((deposit.nsidechain, deposit.address, txdata.len()));
+            txdata.push(tx);
+        }
+        let mut block = bitcoin::Block {
+            header: bitcoin::block::Header {
+                version: bitcoin::block::Version::from_consensus(0x20000000),
+                prev_blockhash,
+                merkle_root: bitcoin::hash_types::TxMerkleNode::all_zeros(),
+                time: height as u32 * 600,
+                bits: bitcoin::CompactTarget::from_consensus(0x207fffff),
+                nonce: self.nonce,
+            },
+            txdata,
+        };
+        block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+        let hash = block.block_hash();
+        self.blocks.insert(
+            hash,
+            SimBlock {
+                block,
+                height,
+                deposits,
+            },
+        );
+        self.active.push(hash);
+        hash
+    }
+
+    fn getblock(&self, hash: &bitcoin::BlockHash) -> Option<Block> {
+        let sim_block = self.blocks.get(hash)?;
+        let block = &sim_block.block;
+        let active = self.is_active(hash);
+        let confirmations = if active {
+            (self.active.len() - sim_block.height) as i64
+        } else {
+            -1
+        };
+        let nextblockhash = if active {
+            self.active.get(sim_block.height + 1).copied()
+        } else {
+            None
+        };
+        let size = bitcoin::consensus::serialize(block).len();
+        let previousblockhash = (sim_block.height > 0).then_some(block.header.prev_blockhash);
+        let time = block.header.time;
+        Some(Block {
+            hash: *hash,
+            confirmations,
+            strippedsize: size,
+            size,
+            weight: block.weight().to_wu() as usize,
+            height: sim_block.height,
+            version: block.header.version.to_consensus(),
+            version_hex: Some(format!("{:08x}", block.header.version.to_consensus())),
+            merkleroot: block.header.merkle_root,
+            tx: block.txdata.iter().map(|tx| tx.txid()).collect(),
+            time,
+            mediantime: Some(time),
+            nonce: block.header.nonce,
+            bits: format!("{:08x}", block.header.bits.to_consensus()),
+            difficulty: Some(block.header.difficulty_float()),
+            chainwork: Some(format!("{:064x}", sim_block.height + 1)),
+            previousblockhash,
+            nextblockhash,
+        })
+    }
+
+    /// A transaction on the active chain, and the block containing it.
+    fn transaction(
+        &self,
+        txid: &bitcoin::Txid,
+    ) -> Option<(&bitcoin::Transaction, bitcoin::BlockHash)> {
+        self.active.iter().find_map(|hash| {
+            let tx = self.blocks[hash]
+                .block
+                .txdata
+                .iter()
+                .find(|tx| tx.txid() == *txid)?;
+            Some((tx, *hash))
+        })
+    }
+
+    fn listsidechaindepositsbyblock(
+        &self,
+        nsidechain: u8,
+        end: Option<bitcoin::BlockHash>,
+        start: Option<bitcoin::BlockHash>,
+    ) -> Option<Vec<Deposit>> {
+        let height = |hash: Option<bitcoin::BlockHash>| match hash {
+            Some(hash) if self.is_active(&hash) => Some(Some(self.blocks[&hash].height)),
+            Some(_) => None,
+            None => Some(None),
+        };
+        let start = height(start)?.unwrap_or(0);
+        let end = height(end)?.unwrap_or(self.active.len() - 1);
+        let mut deposits = Vec::new();
+        for hash in self.active.get(start..=end).unwrap_or_default() {
+            let sim_block = &self.blocks[hash];
+            for (n, address, ntx) in &sim_block.deposits {
+                if *n != nsidechain {
+                    continue;
+                }
+                let mut txhex = vec![];
+                sim_block.block.txdata[*ntx]
+                    .consensus_encode(&mut txhex)
+                    .expect("encoding to a vec cannot fail");
+                deposits.push(Deposit {
+                    hashblock: *hash,
+                    nburnindex: 0,
+                    ntx: *ntx,
+                    strdest: address.clone(),
+                    txhex: hex::encode(txhex),
+                });
+            }
+        }
+        Some(deposits)
+    }
+}
+
+impl Default for SimChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimChain {
+    /// Create a chain containing only a genesis block.
+    pub fn new() -> Self {
+        let mut state = State {
+            blocks: HashMap::new(),
+            active: Vec::new(),
+            mempool: Vec::new(),
+            nonce: 0,
+        };
+        state.mine_one();
+        SimChain(Arc::new(Mutex::new(state)))
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.0.lock().expect("sim chain mutex poisoned")
+    }
+
+    pub fn tip(&self) -> bitcoin::BlockHash {
+        self.state().tip()
+    }
+
+    /// Mine `num` blocks on top of the current tip, including any pending
+    /// deposits in the first one.
+    pub fn mine(&self, num: usize) -> Vec<bitcoin::BlockHash> {
+        let mut state = self.state();
+        (0..num).map(|_| state.mine_one()).collect()
+    }
+
+    /// Queue a deposit to be included in the next mined block.
+    pub fn add_deposit(&self, nsidechain: SidechainId, address: &str, amount: bitcoin::Amount) {
+        self.state().mempool.push(SimDeposit {
+            nsidechain: nsidechain.0,
+            address: address.to_owned(),
+            amount,
+        });
+    }
+
+    /// Disconnect the top `depth` blocks, then mine `num` blocks on the new
+    /// tip. Deposits in the disconnected blocks are dropped. The genesis
+    /// block is never disconnected.
+    pub fn reorg(&self, depth: usize, num: usize) -> Vec<bitcoin::BlockHash> {
+        let mut state = self.state();
+        let keep = state.active.len().saturating_sub(depth).max(1);
+        state.active.truncate(keep);
+        (0..num).map(|_| state.mine_one()).collect()
+    }
+
+    fn dispatch(&self, method: &str, params: &[Value]) -> Result<Value, jsonrpsee::core::Error> {
+        fn param<T: DeserializeOwned>(
+            params: &[Value],
+            index: usize,
+        ) -> Result<T, jsonrpsee::core::Error> {
+            let value = params.get(index).cloned().unwrap_or(Value::Null);
+            serde_json::from_value(value)
+                .map_err(|_| ErrorObject::from(ErrorCode::InvalidParams).into())
+        }
+        let not_found =
+            || jsonrpsee::core::Error::from(ErrorObject::owned(-5, "Block not found", None::<()>));
+        let state = self.state();
+        let result = match method {
+            "getbestblockhash" => serde_json::to_value(state.tip())?,
+            "getblockcount" => serde_json::to_value(state.active.len() - 1)?,
+            "getblockhash" => {
+                let height: usize = param(params, 0)?;
+                let hash = state.active.get(height).ok_or_else(|| {
+                    ErrorObject::owned(-8, "Block height out of range", None::<()>)
+                })?;
+                serde_json::to_value(hash)?
+            }
+            "getblock" => {
+                let hash: bitcoin::BlockHash = param(params, 0)?;
+                let verbosity: Option<usize> = param(params, 1)?;
+                if verbosity == Some(0) {
+                    let sim_block = state.blocks.get(&hash).ok_or_else(not_found)?;
+                    Value::String(bitcoin::consensus::encode::serialize_hex(&sim_block.block))
+                } else {
+                    serde_json::to_value(state.getblock(&hash).ok_or_else(not_found)?)?
+                }
+            }
+            "getblockheader" => {
+                let hash: bitcoin::BlockHash = param(params, 0)?;
+                let verbose: Option<bool> = param(params, 1)?;
+                if verbose != Some(false) {
+                    return Err(ErrorObject::from(ErrorCode::InvalidParams).into());
+                }
+                let sim_block = state.blocks.get(&hash).ok_or_else(not_found)?;
+                Value::String(bitcoin::consensus::encode::serialize_hex(
+                    &sim_block.block.header,
+                ))
+            }
+            "getrawtransaction" => {
+                let txid: bitcoin::Txid = param(params, 0)?;
+                let verbose: Option<bool> = param(params, 1)?;
+                let (tx, hash) = state.transaction(&txid).ok_or_else(|| {
+                    ErrorObject::owned(-5, "No such mempool or blockchain transaction", None::<()>)
+                })?;
+                let hex = bitcoin::consensus::encode::serialize_hex(tx);
+                if verbose == Some(true) {
+                    serde_json::to_value(TransactionInfo {
+                        txid,
+                        hex,
+                        blockhash: Some(hash),
+                        confirmations: Some(
+                            (state.active.len() - state.blocks[&hash].height) as u32,
+                        ),
+                    })?
+                } else {
+                    Value::String(hex)
+                }
+            }
+            "listsidechaindepositsbyblock" => {
+                let deposits = state
+                    .listsidechaindepositsbyblock(
+                        param(params, 0)?,
+                        param(params, 1)?,
+                        param(params, 2)?,
+                    )
+                    .ok_or_else(not_found)?;
+                serde_json::to_value(deposits)?
+            }
+            // Withdrawal bundles are not simulated.
+            "listwithdrawalstatus" | "listspentwithdrawals" | "listfailedwithdrawals" => {
+                Value::Array(Vec::new())
+            }
+            _ => return Err(ErrorObject::from(ErrorCode::MethodNotFound).into()),
+        };
+        Ok(result)
+    }
+}
+
+fn parse_params<Params: ToRpcParams>(params: Params) -> Result<Vec<Value>, jsonrpsee::core::Error> {
+    match params.to_rpc_params()? {
+        Some(raw) => Ok(serde_json::from_str(raw.get())?),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[async_trait::async_trait]
+impl ClientT for SimChain {
+    async fn notification<Params>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<(), jsonrpsee::core::Error>
+    where
+        Params: ToRpcParams + Send,
+    {
+        self.dispatch(method, &parse_params(params)?)?;
+        Ok(())
+    }
+
+    async fn request<R, Params>(
+        &self,
+        method: &str,
+        params: Params,
+    ) -> Result<R, jsonrpsee::core::Error>
+    where
+        R: DeserializeOwned,
+        Params: ToRpcParams + Send,
+    {
+        let result = self.dispatch(method, &parse_params(params)?)?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    async fn batch_request<'a, R>(
+        &self,
+        batch: BatchRequestBuilder<'a>,
+    ) -> Result<BatchResponse<'a, R>, jsonrpsee::core::Error>
+    where
+        R: DeserializeOwned + std::fmt::Debug + 'a,
+    {
+        let mut responses = Vec::new();
+        let mut failed = 0;
+        for (method, params) in batch.build()? {
+            let params = match params {
+                Some(raw) => serde_json::from_str(raw.get())?,
+                None => Vec::new(),
+            };
+            let response = match self.dispatch(method, &params) {
+                Ok(result) => Ok(serde_json::from_value(result)?),
+                Err(jsonrpsee::core::Error::Call(err)) => {
+                    failed += 1;
+                    Err(err)
+                }
+                Err(err) => return Err(err),
+            };
+            responses.push(response);
+        }
+        Ok(BatchResponse::new(
+            responses.len() - failed,
+            responses,
+            failed,
+        ))
+    }
+}
@@ -0,0 +1,150 @@
+//! Persistence of the cursor used to sync two way peg data incrementally.
+use crate::{Drivechain, Error, TwoWayPegData};
+use jsonrpsee::core::client::ClientT;
+
+/// Storage for the cursor of [`Drivechain::sync_with_store`], which is the
+/// `deposit_block_hash` of the last peg data committed.
+pub trait SyncStore {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// The saved cursor, or `None` if nothing has been synced yet.
+    fn load_cursor(&mut self) -> Result<Option<bitcoin::BlockHash>, Self::Error>;
+
+    fn save_cursor(&mut self, cursor: bitcoin::BlockHash) -> Result<(), Self::Error>;
+}
+
+/// A [`SyncStore`] that keeps the cursor in memory.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemorySyncStore {
+    pub cursor: Option<bitcoin::BlockHash>,
+}
+
+impl SyncStore for MemorySyncStore {
+    type Error = std::convert::Infallible;
+
+    fn load_cursor(&mut self) -> Result<Option<bitcoin::BlockHash>, Self::Error> {
+        Ok(self.cursor)
+    }
+
+    fn save_cursor(&mut self, cursor: bitcoin::BlockHash) -> Result<(), Self::Error> {
+        self.cursor = Some(cursor);
+        Ok(())
+    }
+}
+
+/// A [`SyncStore`] that keeps the cursor in a file, as a hex block hash.
+#[cfg(feature = "file-store")]
+#[derive(Clone, Debug)]
+pub struct FileSyncStore {
+    path: std::path::PathBuf,
+}
+
+#[cfg(feature = "file-store")]
+impl FileSyncStore {
+    /// The file need not exist yet.
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        FileSyncStore { path: path.into() }
+    }
+}
+
+#[cfg(feature = "file-store")]
+impl SyncStore for FileSyncStore {
+    type Error = std::io::Error;
+
+    fn load_cursor(&mut self) -> Result<Option<bitcoin::BlockHash>, Self::Error> {
+        let cursor = match std::fs::read_to_string(&self.path) {
+            Ok(cursor) => cursor,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let cursor = cursor
+            .trim()
+            .parse()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        Ok(Some(cursor))
+    }
+
+    /// Writes to a temporary file first, so that the cursor is never left
+    /// partially written.
+    fn save_cursor(&mut self, cursor: bitcoin::BlockHash) -> Result<(), Self::Error> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, cursor.to_string())?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// Peg data fetched by [`Drivechain::sync_with_store`]. The cursor is only
+/// advanced by [`Self::commit`], so that if the data is not processed, eg.
+/// because the process exits first, the next sync fetches it again.
+#[must_use = "the cursor is only saved by commit"]
+pub struct SyncBatch<'a, S> {
+    store: &'a mut S,
+    two_way_peg_data: TwoWayPegData,
+}
+
+impl<S> SyncBatch<'_, S>
+where
+    S: SyncStore,
+{
+    pub fn two_way_peg_data(&self) -> &TwoWayPegData {
+        &self.two_way_peg_data
+    }
+
+    /// Save the advanced cursor, once the data has been processed.
+    pub fn commit(self) -> Result<TwoWayPegData, Error> {
+        if let Some(cursor) = self.two_way_peg_data.deposit_block_hash {
+            self.store
+                .save_cursor(cursor)
+                .map_err(|err| Error::SyncStore(Box::new(err)))?;
+        }
+        Ok(self.two_way_peg_data)
+    }
+}
+
+impl<C> Drivechain<C>
+where
+    C: ClientT + Send + Sync,
+{
+    /// Fetch the peg data since the cursor in `store`, up to the mainchain
+    /// tip. Commit the returned batch after processing it to advance the
+    /// cursor.
+    pub async fn sync_with_store<'a, S>(&self, store: &'a mut S) -> Result<SyncBatch<'a, S>, Error>
+    where
+        S: SyncStore,
+    {
+        let cursor = store
+            .load_cursor()
+            .map_err(|err| Error::SyncStore(Box::new(err)))?;
+        let tip = self.get_mainchain_tip().await?;
+        let two_way_peg_data = self.get_two_way_peg_data(tip, cursor).await?;
+        Ok(SyncBatch {
+            store,
+            two_way_peg_data,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use super::*;
+    use crate::{SidechainId, SimChain};
+
+    #[tokio::test]
+    async fn cursor_advances_on_commit() {
+        let sim = SimChain::new();
+        let drivechain = Drivechain::from_client(SidechainId(0), sim.clone());
+        let mut store = MemorySyncStore::default();
+        sim.add_deposit(SidechainId(0), "a", bitcoin::Amount::from_sat(1000));
+        let block = sim.mine(1)[0];
+        for _ in 0..2 {
+            let batch = drivechain.sync_with_store(&mut store).await.unwrap();
+            assert_eq!(batch.two_way_peg_data().deposits.len(), 1);
+        }
+        let batch = drivechain.sync_with_store(&mut store).await.unwrap();
+        batch.commit().unwrap();
+        assert_eq!(store.cursor, Some(block));
+        let batch = drivechain.sync_with_store(&mut store).await.unwrap();
+        assert!(batch.two_way_peg_data().deposits.is_empty());
+    }
+}
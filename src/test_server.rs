@@ -0,0 +1,154 @@
+//! Local HTTP server answering JSON-RPC requests the way the daemon does,
+//! for testing [`crate::RpcClient`] over real HTTP.
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The HTTP response to a request.
+pub(crate) struct Reply {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Option<Value>,
+}
+
+impl Reply {
+    /// A successful response to `request`.
+    pub fn result(request: &Value, result: Value) -> Self {
+        Reply {
+            status: 200,
+            headers: Vec::new(),
+            body: Some(json!({ "result": result, "error": null, "id": request["id"] })),
+        }
+    }
+
+    /// A failed call, answered with `status` as the daemon does before v28.
+    pub fn error(request: &Value, status: u16, code: i32, message: &str) -> Self {
+        Reply {
+            status,
+            headers: Vec::new(),
+            body: Some(json!({
+                "result": null,
+                "error": { "code": code, "message": message },
+                "id": request["id"],
+            })),
+        }
+    }
+
+    /// A response with `status` and no body, as from a proxy.
+    pub fn status(status: u16) -> Self {
+        Reply {
+            status,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_owned()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&Value) -> Reply + Send + Sync;
+
+/// A running server. Stops when the test's runtime shuts down.
+pub(crate) struct TestServer {
+    /// `hostname:port` to connect to.
+    pub host: String,
+    /// Methods of the requests received, in order.
+    pub methods: Arc<Mutex<Vec<String>>>,
+}
+
+/// Serve requests with `handler`, waiting `delay` before each response.
+/// Batch requests are answered with HTTP 200, with `handler` applied to
+/// each call.
+pub(crate) async fn serve<F>(delay: Duration, handler: F) -> TestServer
+where
+    F: Fn(&Value) -> Reply + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = listener.local_addr().unwrap().to_string();
+    let methods = Arc::new(Mutex::new(Vec::new()));
+    let handler: Arc<Handler> = Arc::new(handler);
+    {
+        let methods = methods.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let handler = handler.clone();
+                let methods = methods.clone();
+                tokio::spawn(async move {
+                    respond(stream, delay, &*handler, &methods).await;
+                });
+            }
+        });
+    }
+    TestServer { host, methods }
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    delay: Duration,
+    handler: &Handler,
+    methods: &Mutex<Vec<String>>,
+) {
+    let request = read_request(&mut stream).await;
+    let calls = match &request {
+        Value::Array(calls) => calls.clone(),
+        call => vec![call.clone()],
+    };
+    methods.lock().unwrap().extend(
+        calls
+            .iter()
+            .map(|call| call["method"].as_str().unwrap_or_default().to_owned()),
+    );
+    let reply = match request {
+        Value::Array(calls) => Reply {
+            status: 200,
+            headers: Vec::new(),
+            body: Some(calls.iter().map(|call| handler(call).body).collect()),
+        },
+        call => handler(&call),
+    };
+    tokio::time::sleep(delay).await;
+    let body = reply.body.map(|body| body.to_string()).unwrap_or_default();
+    let mut response = format!(
+        "HTTP/1.1 {} Status\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n",
+        reply.status,
+        body.len()
+    );
+    for (name, value) in reply.headers {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Read a request, returning its JSON body.
+async fn read_request(stream: &mut TcpStream) -> Value {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    let header_len = loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "connection closed before the end of the headers");
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let headers = String::from_utf8_lossy(&buf[..header_len]).to_ascii_lowercase();
+    let content_length: usize = headers
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length:"))
+        .map_or(0, |len| len.trim().parse().unwrap());
+    while buf.len() < header_len + content_length {
+        let n = stream.read(&mut chunk).await.unwrap();
+        assert!(n > 0, "connection closed before the end of the body");
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    serde_json::from_slice(&buf[header_len..header_len + content_length]).unwrap()
+}